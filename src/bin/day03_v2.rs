@@ -0,0 +1,86 @@
+use anyhow::{ensure, Context, Result};
+use itertools::Itertools;
+
+
+/// Column-wise counter stored bit-sliced: bit j of planes[k] is bit k of the
+/// number of ones seen in column j, so adding a word is a ripple-carry add
+/// over the planes that usually stops after a couple of iterations.
+struct VerticalCounter {
+    planes: Vec<u64>,
+}
+
+impl VerticalCounter {
+    fn new() -> Self {
+        Self { planes: Vec::new() }
+    }
+
+    fn add(&mut self, word: u64) {
+        let mut carry = word;
+        for plane in &mut self.planes {
+            if carry == 0 {
+                return;
+            }
+            let sum = *plane ^ carry;
+            carry &= *plane;
+            *plane = sum;
+        }
+        if carry != 0 {
+            self.planes.push(carry);
+        }
+    }
+
+    fn count(&self, column: usize) -> u64 {
+        let bits = self.planes.iter().map(|p| (p >> column) & 1);
+        bits.enumerate().map(|(k, b)| b << k).sum()
+    }
+}
+
+fn rating(sorted: &[u64], width: usize, keep_most_common: bool) -> Option<u64> {
+    // All words in sorted[lo..hi] share their bits above `bit`, so the ones
+    // with a zero at `bit` form a prefix of the range.
+    let (mut lo, mut hi) = (0, sorted.len());
+    for bit in (0..width).rev() {
+        if hi - lo <= 1 {
+            break;
+        }
+        let split = lo + sorted[lo..hi].partition_point(|x| (x >> bit) & 1 == 0);
+        let (zeros, ones) = (split - lo, hi - split);
+        if (ones >= zeros) == keep_most_common {
+            lo = split;
+        } else {
+            hi = split;
+        }
+    }
+    (hi - lo == 1).then(|| sorted[lo])
+}
+
+fn main() -> Result<()> {
+    let input = std::fs::read_to_string("inputs/day03.txt")?;
+    let start = std::time::Instant::now();
+    let w = input.lines().next().context("empty input")?.len();
+    ensure!((1..=64).contains(&w), "line width must be between 1 and 64 bits");
+
+    let mut counter = VerticalCounter::new();
+    let mut words: Vec<u64> = input
+        .lines()
+        .map(|line| {
+            ensure!(line.len() == w, "inconsistent line widths");
+            let word = u64::from_str_radix(line, 2)?;
+            counter.add(word);
+            Ok(word)
+        })
+        .try_collect()?;
+    let n = words.len() as u64;
+
+    let gamma = (0..w).map(|j| ((2 * counter.count(j) > n) as u64) << j).sum::<u64>();
+    let epsilon = !gamma & (u64::MAX >> (64 - w));
+
+    words.sort_unstable();
+    let oxygen = rating(&words, w, true).context("could not find oxygen")?;
+    let co2_scrubber = rating(&words, w, false).context("could not find co2 scrubber")?;
+    let (part1, part2) = (gamma * epsilon, oxygen * co2_scrubber);
+    println!("time: {:?}", start.elapsed());
+    println!("part1: {}", part1);
+    println!("part2: {}", part2);
+    Ok(())
+}
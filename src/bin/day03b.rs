@@ -1,35 +1,139 @@
 use std::cmp::Ordering;
+use std::str::FromStr;
 
-use anyhow::{Context, Result};
+use anyhow::{bail, ensure, Context, Result};
 use itertools::{partition, Itertools};
 
 
-fn partition_recursive<'a>(v: &mut [&'a str], i: usize, keep_most_common: bool) -> Option<&'a str> {
-    if v.len() <= 1 {
-        return v.get(0).cloned();
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Criterion {
+    MostCommon,
+    LeastCommon,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum OnEmpty {
+    Bail,
+    KeepSurvivors,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct RatingPolicy {
+    criterion: Criterion,
+    tie_bit: u8,
+    on_empty: OnEmpty,
+}
+
+const OXYGEN: RatingPolicy = RatingPolicy {
+    criterion: Criterion::MostCommon,
+    tie_bit: b'1',
+    on_empty: OnEmpty::Bail,
+};
+const CO2_SCRUBBER: RatingPolicy = RatingPolicy {
+    criterion: Criterion::LeastCommon,
+    tie_bit: b'0',
+    on_empty: OnEmpty::Bail,
+};
+
+impl FromStr for RatingPolicy {
+    type Err = anyhow::Error;
+
+    /// Parses policies of the form `most:1:bail` or `least:0:keep`.
+    fn from_str(s: &str) -> Result<Self> {
+        let (criterion, tie_bit, on_empty) =
+            s.split(':').collect_tuple().context("expected criterion:tie:on_empty")?;
+        let criterion = match criterion {
+            "most" => Criterion::MostCommon,
+            "least" => Criterion::LeastCommon,
+            _ => bail!("unknown criterion {:?}", criterion),
+        };
+        let tie_bit = match tie_bit {
+            "0" | "1" => tie_bit.as_bytes()[0],
+            _ => bail!("tie bit must be 0 or 1"),
+        };
+        let on_empty = match on_empty {
+            "bail" => OnEmpty::Bail,
+            "keep" => OnEmpty::KeepSurvivors,
+            _ => bail!("unknown empty policy {:?}", on_empty),
+        };
+        Ok(RatingPolicy {
+            criterion,
+            tie_bit,
+            on_empty,
+        })
     }
+}
 
-    let split = partition(&mut v[..], |s| *s.as_bytes().get(i).unwrap() == b'0');
-    let zero_vs_one = (2 * split).cmp(&v.len());
-    match (keep_most_common, zero_vs_one) {
-        (true, Ordering::Less) | (true, Ordering::Equal) | (false, Ordering::Greater) => {
-            partition_recursive(&mut v[split..], i + 1, keep_most_common)
-        },
-        (false, Ordering::Less) | (false, Ordering::Equal) | (true, Ordering::Greater) => {
-            partition_recursive(&mut v[..split], i + 1, keep_most_common)
-        },
+#[derive(Clone, Debug)]
+struct Rating<'a> {
+    survivors: Vec<&'a str>,
+    /// Number of candidates still alive before filtering on each bit position.
+    candidate_counts: Vec<usize>,
+}
+
+fn filter_rating<'a>(mut v: &mut [&'a str], policy: RatingPolicy) -> Result<Rating<'a>> {
+    let mut candidate_counts = Vec::new();
+    for i in 0.. {
+        candidate_counts.push(v.len());
+        if v.len() <= 1 || v.iter().any(|s| s.len() <= i) {
+            break;
+        }
+
+        let split = partition(&mut v[..], |s| s.as_bytes()[i] == b'0');
+        let keep_ones = match ((v.len() - split).cmp(&split), policy.criterion) {
+            (Ordering::Equal, _) => policy.tie_bit == b'1',
+            (ord, Criterion::MostCommon) => ord == Ordering::Greater,
+            (ord, Criterion::LeastCommon) => ord == Ordering::Less,
+        };
+
+        let (zeros, ones) = std::mem::take(&mut v).split_at_mut(split);
+        let (kept, rest) = if keep_ones {
+            (ones, zeros)
+        } else {
+            (zeros, ones)
+        };
+        if kept.is_empty() {
+            match policy.on_empty {
+                OnEmpty::Bail => bail!("no candidates left at bit {}", i),
+                OnEmpty::KeepSurvivors => {
+                    v = rest;
+                    break;
+                },
+            }
+        }
+        v = kept;
     }
+
+    if policy.on_empty == OnEmpty::Bail {
+        ensure!(!v.is_empty(), "no candidates");
+        ensure!(v.len() == 1, "{} candidates left after filtering", v.len());
+    }
+    Ok(Rating {
+        survivors: v.to_vec(),
+        candidate_counts,
+    })
 }
 
 fn main() -> Result<()> {
+    let mut args = std::env::args().skip(1);
+    let oxygen_policy = args.next().map_or(Ok(OXYGEN), |s| s.parse())?;
+    let co2_policy = args.next().map_or(Ok(CO2_SCRUBBER), |s| s.parse())?;
+
     let input = std::fs::read_to_string("inputs/day03.txt")?;
     let start = std::time::Instant::now();
     let mut lines = input.lines().collect_vec();
-    let oxygen = partition_recursive(&mut lines[..], 0, true).context("could not find oxygen")?;
+    ensure!(lines.iter().map(|l| l.len()).all_equal(), "inconsistent line widths");
+
+    let oxygen = filter_rating(&mut lines[..], oxygen_policy).context("could not find oxygen")?;
     let co2_scrubber =
-        partition_recursive(&mut lines[..], 0, false).context("could not find co2 scrubber")?;
-    let answer = u64::from_str_radix(&oxygen, 2)? * u64::from_str_radix(&co2_scrubber, 2)?;
+        filter_rating(&mut lines[..], co2_policy).context("could not find co2 scrubber")?;
+    let rating_value = |r: &Rating| -> Result<u64> {
+        Ok(u64::from_str_radix(r.survivors.first().context("no survivors")?, 2)?)
+    };
+    let answer = rating_value(&oxygen)? * rating_value(&co2_scrubber)?;
     println!("time: {:?}", start.elapsed());
+    println!("oxygen candidates: {}", oxygen.candidate_counts.iter().join(" -> "));
+    println!("co2 candidates: {}", co2_scrubber.candidate_counts.iter().join(" -> "));
     println!("{}", answer);
     Ok(())
 }
@@ -1,23 +1,176 @@
 use std::collections::HashMap;
+use std::str::FromStr;
 
-use anyhow::{Context, Result};
+use anyhow::{bail, ensure, Context, Result};
 use itertools::Itertools;
 
 
-fn winning_time(board: &[u64], width: usize, draw_time: &HashMap<u64, usize>) -> Option<usize> {
-    let (mut maxcols, mut maxrows) = (vec![0; width], vec![0; width]);
-    for (i, x) in board.iter().enumerate() {
-        let t = *draw_time.get(x).unwrap_or(&usize::MAX);
-        let (c, r) = (i % width, i / width);
-        maxcols[c] = maxcols[c].max(t);
-        maxrows[r] = maxrows[r].max(t);
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum WinRule {
+    Rows,
+    Columns,
+    Diagonals,
+    Corners,
+    FullCard,
+}
+
+impl FromStr for WinRule {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Ok(match s {
+            "rows" => WinRule::Rows,
+            "columns" => WinRule::Columns,
+            "diagonals" => WinRule::Diagonals,
+            "corners" => WinRule::Corners,
+            "full" => WinRule::FullCard,
+            _ => bail!("unknown win rule {:?}", s),
+        })
+    }
+}
+
+#[derive(Clone, Debug)]
+struct Board {
+    width: usize,
+    height: usize,
+    nums: Vec<u64>,
+}
+
+impl Board {
+    /// All sets of cell indices that complete a win under the given rule.
+    fn lines(&self, rule: WinRule) -> Vec<Vec<usize>> {
+        let (w, h) = (self.width, self.height);
+        match rule {
+            WinRule::Rows => (0..h).map(|r| (0..w).map(|c| r * w + c).collect()).collect(),
+            WinRule::Columns => (0..w).map(|c| (0..h).map(|r| r * w + c).collect()).collect(),
+            WinRule::Diagonals if w == h => vec![
+                (0..w).map(|i| i * w + i).collect(),
+                (0..w).map(|i| i * w + (w - 1 - i)).collect(),
+            ],
+            WinRule::Diagonals => Vec::new(),
+            WinRule::Corners => vec![[0, w - 1, (h - 1) * w, h * w - 1].into_iter().collect()],
+            WinRule::FullCard => vec![(0..w * h).collect()],
+        }
+    }
+}
+
+fn parse_boards(s: &str) -> Result<Vec<Board>> {
+    let mut boards = Vec::new();
+    for group in s.split("\n\n").map(str::trim).filter(|g| !g.is_empty()) {
+        let rows: Vec<Vec<u64>> = group
+            .lines()
+            .map(|line| line.split_ascii_whitespace().map(|n| n.parse()).try_collect())
+            .try_collect()?;
+        let width = rows[0].len();
+        ensure!(
+            rows.iter().all(|r| r.len() == width),
+            "board {} is not rectangular",
+            boards.len()
+        );
+        let height = rows.len();
+        boards.push(Board {
+            width,
+            height,
+            nums: rows.concat(),
+        });
+    }
+    Ok(boards)
+}
+
+/// Checks the draws and boards for inconsistencies. Problems that make the
+/// game ill-defined are errors, the rest is returned as warnings.
+fn validate(draws: &[u64], boards: &[Board]) -> Result<Vec<String>> {
+    let mut warnings = Vec::new();
+    let repeated = draws.iter().duplicates().collect_vec();
+    if !repeated.is_empty() {
+        warnings.push(format!("numbers drawn more than once: {:?}", repeated));
     }
 
-    let win_t = maxcols.into_iter().chain(maxrows).min().unwrap();
-    (win_t < usize::MAX).then(|| win_t)
+    if let [first, full @ .., last] = boards {
+        let (w, h) = (first.width, first.height);
+        let uniform = full.iter().all(|b| (b.width, b.height) == (w, h));
+        let partial = uniform && last.width == w && last.height < h;
+        ensure!(!partial, "trailing partial board with {} of {} rows", last.height, h);
+        for (i, board) in boards.iter().enumerate().skip(1) {
+            if (board.width, board.height) != (w, h) {
+                let (bw, bh) = (board.width, board.height);
+                warnings
+                    .push(format!("board {} is {}x{} unlike the {}x{} board 0", i, bw, bh, w, h));
+            }
+        }
+    }
+
+    for (i, board) in boards.iter().enumerate() {
+        let dups = board.nums.iter().duplicates().collect_vec();
+        ensure!(dups.is_empty(), "board {} contains duplicate numbers {:?}", i, dups);
+        let undrawn = board.nums.iter().filter(|x| !draws.contains(x)).collect_vec();
+        if !undrawn.is_empty() {
+            warnings.push(format!("board {} has numbers never drawn: {:?}", i, undrawn));
+        }
+    }
+    Ok(warnings)
+}
+
+/// Returns the draw index at which the board wins together with the line that
+/// completed first, if the board wins at all.
+fn winning_time(
+    board: &Board,
+    rules: &[WinRule],
+    draw_time: &HashMap<u64, usize>,
+) -> Option<(usize, Vec<usize>)> {
+    let t = board
+        .nums
+        .iter()
+        .map(|x| *draw_time.get(x).unwrap_or(&usize::MAX))
+        .collect_vec();
+    let lines = rules.iter().flat_map(|r| board.lines(*r));
+    let (win_t, line) = lines
+        .map(|line| (line.iter().map(|i| t[*i]).max().unwrap_or(0), line))
+        .min_by_key(|(win_t, _)| *win_t)?;
+    (win_t < usize::MAX).then_some((win_t, line))
+}
+
+#[derive(Clone, Debug)]
+struct Win {
+    board: usize,
+    time: usize,
+    line: Vec<usize>,
+    score: u64,
+}
+
+fn print_replay(board: &Board, rules: &[WinRule], draws: &[u64]) {
+    let mut draw_t: HashMap<u64, usize> = HashMap::new();
+    for (i, draw) in draws.iter().enumerate() {
+        draw_t.entry(*draw).or_insert(i);
+        println!("draw {}: {}", i, draw);
+        for row in board.nums.chunks(board.width) {
+            let cells = row.iter().map(|x| {
+                let mark = if draw_t.contains_key(x) { '*' } else { ' ' };
+                format!("{:>3}{}", x, mark)
+            });
+            println!("{}", cells.collect::<String>());
+        }
+        if winning_time(board, rules, &draw_t).is_some() {
+            println!("board wins on draw {}", i);
+            return;
+        }
+    }
+    println!("board never wins");
 }
 
 fn main() -> Result<()> {
+    let mut rules = vec![WinRule::Rows, WinRule::Columns];
+    let mut show_ranking = false;
+    let mut replay: Option<usize> = None;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--ranking" => show_ranking = true,
+            "--replay" => replay = Some(args.next().context("--replay needs a board")?.parse()?),
+            _ => rules = arg.split(',').map(str::parse).try_collect()?,
+        }
+    }
+
     let input = std::fs::read_to_string("inputs/day04.txt")?;
     let start = std::time::Instant::now();
     let (drawline, rest) = input.split_once('\n').context("no newline")?;
@@ -28,17 +181,55 @@ fn main() -> Result<()> {
         draw_t.entry(*draw).or_insert(i);
     }
 
-    let boardnums: Vec<u64> = rest.split_ascii_whitespace().map(|n| n.parse()).try_collect()?;
-    let winning_times =
-        boardnums.chunks_exact(25).flat_map(|b| Some((winning_time(b, 5, &draw_t)?, b)));
-    let (min, max) = winning_times.minmax().into_option().context("no winning board")?;
+    let boards = parse_boards(rest)?;
+    for warning in validate(&draws, &boards)? {
+        eprintln!("warning: {}", warning);
+    }
+
+    let mut ranking = Vec::new();
+    let mut never_wins = Vec::new();
+    for (i, board) in boards.iter().enumerate() {
+        match winning_time(board, &rules, &draw_t) {
+            Some((time, line)) => {
+                let unmarked =
+                    board.nums.iter().filter(|x| draw_t.get(x).is_none_or(|t| *t > time));
+                let score = draws[time] * unmarked.sum::<u64>();
+                ranking.push(Win {
+                    board: i,
+                    time,
+                    line,
+                    score,
+                });
+            },
+            None => never_wins.push(i),
+        }
+    }
+    ranking.sort_by_key(|win| (win.time, win.board));
 
-    let score = |(win_t, board): (usize, &[u64])| {
-        draws[win_t] * board.iter().filter(|x| draw_t[x] > win_t).sum::<u64>()
-    };
-    let (part1, part2) = (score(min), score(max));
+    let part1 = ranking.first().context("no winning board")?.score;
+    let part2 = ranking.last().context("no winning board")?.score;
     println!("time: {:?}", start.elapsed());
     println!("part1: {}", part1);
     println!("part2: {}", part2);
+
+    if show_ranking {
+        for (rank, win) in ranking.iter().enumerate() {
+            let line = win.line.iter().map(|i| boards[win.board].nums[*i]).join(" ");
+            println!(
+                "#{} board {} wins on draw {} ({}) with [{}], score {}",
+                rank + 1,
+                win.board,
+                win.time,
+                draws[win.time],
+                line,
+                win.score
+            );
+        }
+        println!("never win: {:?}", never_wins);
+    }
+    if let Some(b) = replay {
+        let board = boards.get(b).with_context(|| format!("no board {}", b))?;
+        print_replay(board, &rules, &draws);
+    }
     Ok(())
 }
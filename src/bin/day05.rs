@@ -1,53 +1,382 @@
-use std::cmp;
+use std::str::FromStr;
 
-use anyhow::{Context, Ok, Result};
-use hashbrown::HashMap;
-use itertools::Either::{Left, Right};
+use anyhow::{bail, ensure, Context, Ok, Result};
+use hashbrown::{HashMap, HashSet};
 use itertools::Itertools;
 
 
 #[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
 struct Point(i16, i16);
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum LineClass {
+    Horizontal,
+    Vertical,
+    Diagonal,
+    Other,
+}
+
+impl FromStr for LineClass {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Ok(match s {
+            "horizontal" => LineClass::Horizontal,
+            "vertical" => LineClass::Vertical,
+            "diagonal" => LineClass::Diagonal,
+            "other" => LineClass::Other,
+            _ => bail!("unknown line class {:?}", s),
+        })
+    }
+}
+
+/// Differences between i64 coordinates, which don't necessarily fit an i64.
+fn delta(p: (i64, i64), q: (i64, i64)) -> (i128, i128) {
+    (q.0 as i128 - p.0 as i128, q.1 as i128 - p.1 as i128)
+}
+
+fn classify(p: (i64, i64), q: (i64, i64)) -> LineClass {
+    let (dx, dy) = delta(p, q);
+    if dy == 0 {
+        LineClass::Horizontal
+    } else if dx == 0 {
+        LineClass::Vertical
+    } else if dx.abs() == dy.abs() {
+        LineClass::Diagonal
+    } else {
+        LineClass::Other
+    }
+}
+
+fn gcd(a: i128, b: i128) -> i128 {
+    if b == 0 {
+        a.abs()
+    } else {
+        gcd(b, a % b)
+    }
+}
+
+/// Enumerates all lattice points on the segment from p to q, in order.
 fn interpolate(p: Point, q: Point) -> impl Iterator<Item = Point> {
-    let symmetric_range = |a, b| {
-        if a <= b {
-            Left(a..=b)
-        } else {
-            Right((b..=a).rev())
+    let (dx, dy) = (q.0 as i64 - p.0 as i64, q.1 as i64 - p.1 as i64);
+    let steps = gcd(dx.into(), dy.into()) as i64;
+    let (sx, sy) = if steps == 0 {
+        (0, 0)
+    } else {
+        (dx / steps, dy / steps)
+    };
+    (0..=steps).map(move |i| Point((p.0 as i64 + i * sx) as i16, (p.1 as i64 + i * sy) as i16))
+}
+
+/// Rasterized overlap density of a set of vent lines, each with its 1-based
+/// line number in the input.
+struct VentMap {
+    lines: Vec<(usize, Point, Point)>,
+    density: HashMap<Point, u32>,
+}
+
+impl VentMap {
+    fn new(lines: impl IntoIterator<Item = (usize, Point, Point)>) -> Self {
+        let lines = lines.into_iter().collect_vec();
+        let mut density = HashMap::new();
+        for p in lines.iter().flat_map(|(_, p, q)| interpolate(*p, *q)) {
+            *density.entry(p).or_insert(0) += 1;
         }
+        Self { lines, density }
+    }
+
+    fn count_at_least(&self, k: u32) -> usize {
+        self.density.values().filter(|n| **n >= k).count()
+    }
+
+    /// Line numbers of the lines passing through p.
+    fn lines_covering(&self, p: Point) -> Vec<usize> {
+        let covers = |(_, a, b): &&(usize, Point, Point)| interpolate(*a, *b).any(|r| r == p);
+        self.lines.iter().filter(covers).map(|(nr, _, _)| *nr).collect()
+    }
+
+    fn bounds(&self) -> Option<(Point, Point)> {
+        let (xmin, xmax) = self.density.keys().map(|p| p.0).minmax().into_option()?;
+        let (ymin, ymax) = self.density.keys().map(|p| p.1).minmax().into_option()?;
+        Some((Point(xmin, ymin), Point(xmax, ymax)))
+    }
+
+    fn rows(&self) -> Vec<Vec<u32>> {
+        let Some((lo, hi)) = self.bounds() else {
+            return Vec::new();
+        };
+        let row = |y| (lo.0..=hi.0).map(move |x| *self.density.get(&Point(x, y)).unwrap_or(&0));
+        (lo.1..=hi.1).map(|y| row(y).collect()).collect()
+    }
+
+    fn to_ascii(&self) -> String {
+        let cell = |n: u32| match n {
+            0 => '.',
+            1..=9 => char::from_digit(n, 10).unwrap(),
+            _ => '#',
+        };
+        self.rows()
+            .iter()
+            .map(|row| row.iter().map(|n| cell(*n)).join("") + "\n")
+            .collect()
+    }
+
+    /// Plain grayscale PGM, brighter meaning more overlapping lines.
+    fn to_pgm(&self) -> String {
+        let rows = self.rows();
+        let max = rows.iter().flatten().copied().max().unwrap_or(0).max(1);
+        let width = rows.first().map_or(0, |r| r.len());
+        let mut out = format!("P2\n{} {}\n{}\n", width, rows.len(), max);
+        for row in rows {
+            out += &row.iter().join(" ");
+            out += "\n";
+        }
+        out
+    }
+}
+
+/// The four line directions as coefficients (a, b) of a*x + b*y = key:
+/// horizontal, vertical, diagonal and anti-diagonal.
+const FAMILIES: [(i128, i128); 4] = [(0, 1), (1, 0), (1, -1), (1, 1)];
+
+/// A segment as family index, key within that family and a closed range of
+/// the parameter t along the line (x, or y for vertical lines). These are
+/// i128 so that keys, and ends past the last point, of i64 lines fit.
+#[derive(Clone, Copy, Debug)]
+struct Segment {
+    family: usize,
+    key: i128,
+    lo: i128,
+    hi: i128,
+}
+
+fn param(family: usize, (x, y): (i128, i128)) -> i128 {
+    if family == 1 {
+        y
+    } else {
+        x
+    }
+}
+
+fn to_segment(p: (i64, i64), q: (i64, i64)) -> Option<Segment> {
+    let (dx, dy) = delta(p, q);
+    let (p, q) = ((p.0 as i128, p.1 as i128), (q.0 as i128, q.1 as i128));
+    let family = FAMILIES.iter().position(|(a, b)| a * dx + b * dy == 0)?;
+    let (a, b) = FAMILIES[family];
+    let (t1, t2) = (param(family, p), param(family, q));
+    Some(Segment {
+        family,
+        key: a * p.0 + b * p.1,
+        lo: t1.min(t2),
+        hi: t1.max(t2),
+    })
+}
+
+/// Lattice point where two segments of different families cross, if any.
+fn crossing(s: &Segment, r: &Segment) -> Option<(i128, i128)> {
+    let ((a1, b1), (a2, b2)) = (FAMILIES[s.family], FAMILIES[r.family]);
+    let det = a1 * b2 - a2 * b1;
+    let (xn, yn) = (s.key * b2 - r.key * b1, a1 * r.key - a2 * s.key);
+    if xn % det != 0 || yn % det != 0 {
+        return None;
+    }
+    let p = (xn / det, yn / det);
+    let on = |seg: &Segment| (seg.lo..=seg.hi).contains(&param(seg.family, p));
+    (on(s) && on(r)).then_some(p)
+}
+
+/// Counts lattice points covered by at least two horizontal, vertical or
+/// diagonal segments without rasterizing. Overlaps within a family are 1D
+/// interval sweeps per key, crossings between families are single points.
+fn num_intersections_sweep(segments: &[Segment]) -> u128 {
+    let mut by_line: HashMap<(usize, i128), Vec<(i128, i64)>> = HashMap::new();
+    for s in segments {
+        let events = by_line.entry((s.family, s.key)).or_default();
+        events.extend([(s.lo, 1), (s.hi + 1, -1)]);
+    }
+
+    let mut total = 0;
+    let mut overlaps: HashMap<(usize, i128), Vec<(i128, i128)>> = HashMap::new();
+    for (line, mut events) in by_line {
+        events.sort_unstable();
+        let mut depth = 0;
+        for ((t, delta), (next_t, _)) in events.iter().tuple_windows() {
+            depth += delta;
+            if depth >= 2 && next_t > t {
+                total += (next_t - t) as u128;
+                overlaps.entry(line).or_default().push((*t, next_t - 1));
+            }
+        }
+    }
+
+    // Number of families in which p lies on an overlap interval.
+    let num_covering = |p: (i128, i128)| {
+        let covering = FAMILIES.iter().enumerate().filter(|(family, (a, b))| {
+            let intervals = overlaps.get(&(*family, a * p.0 + b * p.1));
+            let t = param(*family, p);
+            intervals.is_some_and(|iv| {
+                let i = iv.partition_point(|(_, hi)| *hi < t);
+                iv.get(i).is_some_and(|(lo, _)| *lo <= t)
+            })
+        });
+        covering.count() as u128
     };
-    let xs = symmetric_range(p.0, q.0).cycle();
-    let ys = symmetric_range(p.1, q.1).cycle();
-    let len = 1 + cmp::max((p.0 - q.0).abs(), (p.1 - q.1).abs());
-    xs.zip(ys).map(|(x, y)| Point(x, y)).take(len as usize)
+
+    // Every point covered in more than one family is a crossing as well, so
+    // correcting each distinct crossing by 1 - num_covering counts it once.
+    let mut crossings = HashSet::new();
+    for (i, s) in segments.iter().enumerate() {
+        for r in &segments[i + 1..] {
+            if s.family != r.family {
+                crossings.extend(crossing(s, r));
+            }
+        }
+    }
+    let covered_twice: u128 = crossings.iter().map(|p| num_covering(*p).saturating_sub(1)).sum();
+    let uncovered = crossings.iter().filter(|p| num_covering(**p) == 0).count() as u128;
+    total + uncovered - covered_twice
 }
 
-fn num_intersections(lines: impl IntoIterator<Item = (Point, Point)>) -> usize {
-    let mut grid = HashMap::new();
-    for p in lines.into_iter().flat_map(|(p, q)| interpolate(p, q)) {
-        *grid.entry(p).or_insert(0) += 1;
+/// Compares the sweep against rasterizing on rounds of generated lines,
+/// each round being a number of random horizontal, vertical and diagonal
+/// lines in a small grid so that they overlap a lot.
+fn check(rounds: usize) -> Result<()> {
+    let mut state = 0u64;
+    let mut rand = |n: i64| {
+        state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+        ((state >> 33) % n as u64) as i64
+    };
+    for round in 0..rounds {
+        let mut lines = Vec::new();
+        for _ in 0..1 + rand(50) {
+            let p = (rand(40) - 20, rand(40) - 20);
+            let len = rand(20);
+            let (dx, dy) = [(1, 0), (0, 1), (1, 1), (1, -1)][rand(4) as usize];
+            let sign = if rand(2) == 0 { 1 } else { -1 };
+            lines.push((p, (p.0 + sign * dx * len, p.1 + sign * dy * len)));
+        }
+        let segments = lines.iter().map(|(p, q)| to_segment(*p, *q).unwrap()).collect_vec();
+        let swept = num_intersections_sweep(&segments);
+        let to_point = |(x, y): (i64, i64)| Point(x as i16, y as i16);
+        let lines = lines.iter().enumerate();
+        let raster = VentMap::new(lines.map(|(i, (p, q))| (i + 1, to_point(*p), to_point(*q))));
+        let expected = raster.count_at_least(2) as u128;
+        ensure!(swept == expected, "round {}: sweep {} != raster {}", round, swept, expected);
     }
-    grid.values().filter(|n| **n >= 2).count()
+    println!("{} rounds agree", rounds);
+    Ok(())
+}
+
+/// Queries on the rasterized lines of the selected classes.
+enum Query {
+    AtLeast(u32),
+    Covering(Point),
+    Ascii,
+    Pgm(String),
 }
 
 fn main() -> Result<()> {
+    let mut classes = None;
+    let mut queries = Vec::new();
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut next = |what| args.next().with_context(|| format!("{} needs {}", arg, what));
+        match arg.as_str() {
+            "--check" => return check(next("a round count")?.parse()?),
+            "--classes" => {
+                let list: Vec<LineClass> =
+                    next("classes")?.split(',').map(str::parse).try_collect()?;
+                classes = Some(list);
+            },
+            "--at-least" => queries.push(Query::AtLeast(next("k")?.parse()?)),
+            "--covering" => {
+                let p = next("x,y")?;
+                let (x, y) = p.split_once(',').context("invalid point")?;
+                queries.push(Query::Covering(Point(x.parse()?, y.parse()?)));
+            },
+            "--ascii" => queries.push(Query::Ascii),
+            "--pgm" => queries.push(Query::Pgm(next("a path")?)),
+            _ => bail!("unknown argument {:?}", arg),
+        }
+    }
+
     let input = std::fs::read_to_string("inputs/day05.txt")?;
     let start = std::time::Instant::now();
-    let lines: Vec<(Point, Point)> = input
+    let lines: Vec<((i64, i64), (i64, i64))> = input
         .lines()
         .map(|line| {
             let (from, to) = line.split_once(" -> ").context("invalid line")?;
             let (x1, y1) = from.split_once(",").context("invalid point")?;
             let (x2, y2) = to.split_once(",").context("invalid point")?;
-            Ok((Point(x1.parse()?, y1.parse()?), Point(x2.parse()?, y2.parse()?)))
+            Ok(((x1.parse()?, y1.parse()?), (x2.parse()?, y2.parse()?)))
         })
         .try_collect()?;
 
-    let axis_aligned = lines.iter().copied().filter(|(p, q)| p.0 == q.0 || p.1 == q.1);
-    let (part1, part2) = (num_intersections(axis_aligned), num_intersections(lines));
-    println!("time: {:?}", start.elapsed());
-    println!("part1: {}", part1);
-    println!("part2: {}", part2);
+    // Lines of the given classes with their 1-based line numbers.
+    let of_classes = |classes: &[LineClass]| {
+        let numbered = lines.iter().enumerate().map(|(i, (p, q))| (i + 1, *p, *q));
+        numbered.filter(|(_, p, q)| classes.contains(&classify(*p, *q))).collect_vec()
+    };
+    let sweep = |classes: &[LineClass]| -> Result<u128> {
+        let segments: Vec<Segment> = of_classes(classes)
+            .into_iter()
+            .map(|(_, p, q)| to_segment(p, q).context("unsupported slope"))
+            .try_collect()?;
+        Ok(num_intersections_sweep(&segments))
+    };
+
+    // Rasterizing needs the coordinates to fit a Point.
+    let to_point = |(x, y): &(i64, i64)| Ok(Point((*x).try_into()?, (*y).try_into()?));
+    let raster = |classes: &[LineClass]| -> Result<VentMap> {
+        let small_lines: Vec<_> = of_classes(classes)
+            .iter()
+            .map(|(nr, p, q)| Ok((*nr, to_point(p)?, to_point(q)?)))
+            .try_collect()
+            .context("rasterizing needs coordinates within 16 bits")?;
+        Ok(VentMap::new(small_lines))
+    };
+
+    use LineClass::*;
+    match &classes {
+        None => {
+            let part1 = sweep(&[Horizontal, Vertical])?;
+            let part2 = sweep(&[Horizontal, Vertical, Diagonal])?;
+            println!("time: {:?}", start.elapsed());
+            println!("part1: {}", part1);
+            println!("part2: {}", part2);
+        },
+        Some(classes) => {
+            // Only lines of other slopes need rasterizing.
+            let overlapping = if classes.contains(&Other) {
+                raster(classes)?.count_at_least(2) as u128
+            } else {
+                sweep(classes)?
+            };
+            println!("time: {:?}", start.elapsed());
+            println!("overlapping points: {}", overlapping);
+        },
+    }
+
+    for (i, (p, q)) in lines.iter().enumerate() {
+        let (dx, dy) = delta(*p, *q);
+        if gcd(dx, dy) == 1 && classify(*p, *q) == Other {
+            eprintln!("warning: line {} has no lattice points besides its endpoints", i + 1);
+        }
+    }
+
+    let classes = classes.unwrap_or_else(|| vec![Horizontal, Vertical, Diagonal]);
+    for query in queries {
+        match query {
+            Query::AtLeast(k) => {
+                println!("points with >= {} overlaps: {}", k, raster(&classes)?.count_at_least(k));
+            },
+            Query::Covering(p) => {
+                let covering = raster(&classes)?.lines_covering(p);
+                println!("lines covering {},{}: {:?}", p.0, p.1, covering);
+            },
+            Query::Ascii => print!("{}", raster(&classes)?.to_ascii()),
+            Query::Pgm(path) => std::fs::write(path, raster(&classes)?.to_pgm())?,
+        }
+    }
     Ok(())
 }
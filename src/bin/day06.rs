@@ -1,10 +1,10 @@
-use anyhow::Result;
+use anyhow::{bail, ensure, Context, Result};
+use itertools::Itertools;
+use num_bigint::BigUint;
 
 
+/// Reference implementation, simulating day by day.
 fn population_after(mut fish: [usize; 9], n: usize) -> usize {
-    // Could be O(d^3 log(n)) with d = 9 fast matrix exponentiation or using
-    // "An effficient formula for linear recurrences" by C. Fiduccia in
-    // O(d log(d) log(n)).
     for day in 0..n {
         fish[(day + 7) % 9] += fish[day % 9];
     }
@@ -12,18 +12,253 @@ fn population_after(mut fish: [usize; 9], n: usize) -> usize {
     fish.into_iter().sum::<usize>()
 }
 
+/// The arithmetic the closed forms are evaluated in.
+trait Ring {
+    type Elem: Clone;
+    fn lift(&self, x: u64) -> Self::Elem;
+    fn add(&self, a: &Self::Elem, b: &Self::Elem) -> Self::Elem;
+    fn mul(&self, a: &Self::Elem, b: &Self::Elem) -> Self::Elem;
+}
+
+struct Exact;
+
+impl Ring for Exact {
+    type Elem = BigUint;
+
+    fn lift(&self, x: u64) -> BigUint {
+        BigUint::from(x)
+    }
+
+    fn add(&self, a: &BigUint, b: &BigUint) -> BigUint {
+        a + b
+    }
+
+    fn mul(&self, a: &BigUint, b: &BigUint) -> BigUint {
+        a * b
+    }
+}
+
+/// Integers modulo m. Neither closed form divides, so m need not be prime.
+struct Modulo(u64);
+
+impl Ring for Modulo {
+    type Elem = u64;
+
+    fn lift(&self, x: u64) -> u64 {
+        x % self.0
+    }
+
+    fn add(&self, a: &u64, b: &u64) -> u64 {
+        ((*a as u128 + *b as u128) % self.0 as u128) as u64
+    }
+
+    fn mul(&self, a: &u64, b: &u64) -> u64 {
+        ((*a as u128 * *b as u128) % self.0 as u128) as u64
+    }
+}
+
+/// Population after n days in O(d^3 log(n)) with d = 9 by exponentiating the
+/// transition matrix of the timer counts.
+fn population_after_matrix<R: Ring>(ring: &R, fish: [usize; 9], mut n: u64) -> R::Elem {
+    type Matrix<T> = Vec<Vec<T>>;
+    let matmul = |a: &Matrix<R::Elem>, b: &Matrix<R::Elem>| -> Matrix<R::Elem> {
+        let entry = |i: usize, j: usize| {
+            let terms = (0..9).map(|k| ring.mul(&a[i][k], &b[k][j]));
+            terms.fold(ring.lift(0), |acc, t| ring.add(&acc, &t))
+        };
+        (0..9).map(|i| (0..9).map(|j| entry(i, j)).collect()).collect()
+    };
+
+    // new[i] = old[i + 1], new[6] += old[0], new[8] = old[0].
+    let edge = |i: usize, j: usize| j == (i + 1) % 9 || (i, j) == (6, 0);
+    let mut base: Matrix<R::Elem> =
+        (0..9).map(|i| (0..9).map(|j| ring.lift(edge(i, j) as u64)).collect()).collect();
+    let mut result: Matrix<R::Elem> =
+        (0..9).map(|i| (0..9).map(|j| ring.lift((i == j) as u64)).collect()).collect();
+    while n > 0 {
+        if n % 2 == 1 {
+            result = matmul(&result, &base);
+        }
+        base = matmul(&base, &base);
+        n /= 2;
+    }
+
+    let mut total = ring.lift(0);
+    for row in &result {
+        for (m, count) in row.iter().zip(fish) {
+            total = ring.add(&total, &ring.mul(m, &ring.lift(count as u64)));
+        }
+    }
+    total
+}
+
+/// Population after n days in O(d^2 log(n)) using "An effficient formula for
+/// linear recurrences" by C. Fiduccia. The transition matrix satisfies its
+/// characteristic polynomial x^9 - x^2 - 1, so if x^n = sum(a_k x^k) modulo
+/// that polynomial the population after n days is sum(a_k population(k)).
+fn population_after_fiduccia<R: Ring>(ring: &R, fish: [usize; 9], mut n: u64) -> R::Elem {
+    let mulmod = |a: &[R::Elem], b: &[R::Elem]| -> Vec<R::Elem> {
+        let mut c = vec![ring.lift(0); 17];
+        for (i, x) in a.iter().enumerate() {
+            for (j, y) in b.iter().enumerate() {
+                c[i + j] = ring.add(&c[i + j], &ring.mul(x, y));
+            }
+        }
+        // Reduce with x^9 = x^2 + 1.
+        for i in (9..17).rev() {
+            let hi = c[i].clone();
+            c[i - 7] = ring.add(&c[i - 7], &hi);
+            c[i - 9] = ring.add(&c[i - 9], &hi);
+        }
+        c.truncate(9);
+        c
+    };
+
+    let mut base = (0..9).map(|k| ring.lift((k == 1) as u64)).collect::<Vec<_>>();
+    let mut result = (0..9).map(|k| ring.lift((k == 0) as u64)).collect::<Vec<_>>();
+    while n > 0 {
+        if n % 2 == 1 {
+            result = mulmod(&result, &base);
+        }
+        base = mulmod(&base, &base);
+        n /= 2;
+    }
+
+    let mut total = ring.lift(0);
+    for (k, a) in result.iter().enumerate() {
+        let initial = ring.lift(population_after(fish, k) as u64);
+        total = ring.add(&total, &ring.mul(a, &initial));
+    }
+    total
+}
+
+/// Lanternfish life cycle: a fish first reproduces `newborn_delay` days after
+/// birth, then every `reset` days, and dies upon reaching `death_age` if set.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Lifecycle {
+    reset: usize,
+    newborn_delay: usize,
+    death_age: Option<usize>,
+}
+
+const DEFAULT_LIFECYCLE: Lifecycle = Lifecycle {
+    reset: 7,
+    newborn_delay: 9,
+    death_age: None,
+};
+
+impl Lifecycle {
+    /// Population at the end of each day 0..=days. A fish with timer t is
+    /// treated as born t + 1 - newborn_delay days ago, so ages are as young as
+    /// its timer allows.
+    fn population_series(&self, timers: &[usize], days: usize) -> Result<Vec<u64>> {
+        let (r, d) = (self.reset, self.newborn_delay);
+        let death = self.death_age.unwrap_or(usize::MAX);
+        ensure!(r >= 1 && d >= 1, "periods must be positive");
+        ensure!(death >= d, "fish must live at least the newborn delay");
+        ensure!(timers.iter().all(|t| *t < d), "timers must be less than the newborn delay");
+
+        // births[i] is the number of fish born on day i - d, parents[k] the
+        // number of living mature fish whose birth index is k modulo r.
+        let mut births = vec![0u64; d + days + 1];
+        for t in timers {
+            births[t + 1] += 1;
+        }
+        let mut parents = vec![0u64; r];
+        let mut alive = timers.len() as u64;
+        let mut series = vec![alive];
+        for day in 1..=days {
+            let s = day + d;
+            if d < death {
+                parents[day % r] += births[day];
+            }
+            if let Some(b) = s.checked_sub(death) {
+                alive -= births[b];
+                if d < death {
+                    parents[b % r] -= births[b];
+                }
+            }
+            births[s] = parents[day % r];
+            alive = alive.checked_add(births[s]).context("population overflows u64")?;
+            series.push(alive);
+        }
+        Ok(series)
+    }
+}
+
 fn main() -> Result<()> {
+    let mut lifecycle = DEFAULT_LIFECYCLE;
+    let mut targets = vec![80, 256];
+    let mut csv_path = None;
+    let mut closed_form_days = None;
+    let mut modulus = None;
+    let mut use_matrix = false;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().with_context(|| format!("{} needs a value", arg));
+        match arg.as_str() {
+            "--reset" => lifecycle.reset = value()?.parse()?,
+            "--newborn" => lifecycle.newborn_delay = value()?.parse()?,
+            "--death" => lifecycle.death_age = Some(value()?.parse()?),
+            "--days" => targets = value()?.split(',').map(str::parse).try_collect()?,
+            "--csv" => csv_path = Some(value()?),
+            "--closed-form" => closed_form_days = Some(value()?.parse::<u64>()?),
+            "--modulus" => modulus = Some(value()?.parse::<u64>()?),
+            "--matrix" => use_matrix = true,
+            _ => bail!("unknown argument {:?}", arg),
+        }
+    }
+
     let input = std::fs::read_to_string("inputs/day06.txt")?;
     let start = std::time::Instant::now();
-    let mut fish = [0usize; 9];
-    for age in input.split(',') {
-        let age: usize = age.trim().parse()?;
-        fish[age] += 1;
+    let timers: Vec<usize> = input.trim().split(',').map(str::parse).try_collect()?;
+    let max_days = targets.iter().copied().max().unwrap_or(0);
+    let series = lifecycle.population_series(&timers, max_days)?;
+    println!("time: {:?}", start.elapsed());
+    if targets == [80, 256] {
+        println!("part1: {}", series[80]);
+        println!("part2: {}", series[256]);
+    } else {
+        for days in &targets {
+            println!("after {} days: {}", days, series[*days]);
+        }
     }
 
-    let (part1, part2) = (population_after(fish, 80), population_after(fish, 256));
-    println!("time: {:?}", start.elapsed());
-    println!("part1: {}", part1);
-    println!("part2: {}", part2);
+    if lifecycle == DEFAULT_LIFECYCLE {
+        let mut fish = [0usize; 9];
+        for t in &timers {
+            fish[*t] += 1;
+        }
+        for days in &targets {
+            let reference = population_after(fish, *days) as u64;
+            ensure!(series[*days] == reference, "series disagrees with simulation");
+        }
+
+        if let Some(days) = closed_form_days {
+            let start = std::time::Instant::now();
+            let population = match modulus {
+                Some(m) => {
+                    ensure!(m >= 2, "modulus must be at least 2");
+                    let ring = Modulo(m);
+                    if use_matrix {
+                        population_after_matrix(&ring, fish, days).to_string()
+                    } else {
+                        population_after_fiduccia(&ring, fish, days).to_string()
+                    }
+                },
+                None if use_matrix => population_after_matrix(&Exact, fish, days).to_string(),
+                None => population_after_fiduccia(&Exact, fish, days).to_string(),
+            };
+            println!("time: {:?}", start.elapsed());
+            println!("after {} days: {}", days, population);
+        }
+    } else {
+        ensure!(closed_form_days.is_none(), "closed forms need the default life cycle");
+    }
+
+    if let Some(path) = csv_path {
+        let rows = series.iter().enumerate().map(|(day, n)| format!("{},{}\n", day, n));
+        std::fs::write(path, String::from("day,population\n") + &rows.collect::<String>())?;
+    }
     Ok(())
 }
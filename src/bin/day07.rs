@@ -0,0 +1,200 @@
+use std::str::FromStr;
+
+use anyhow::{ensure, Context, Result};
+use itertools::Itertools;
+
+
+/// Fuel a single crab spends to move a given distance.
+#[derive(Clone, Debug)]
+enum Cost {
+    Linear,
+    Triangular,
+    Quadratic,
+    /// Cost per distance, must cover every distance that can occur.
+    Table(Vec<i64>),
+}
+
+impl Cost {
+    fn fuel(&self, dist: i64) -> i64 {
+        match self {
+            Cost::Linear => dist,
+            Cost::Triangular => dist * (dist + 1) / 2,
+            Cost::Quadratic => dist * dist,
+            Cost::Table(table) => table[dist as usize],
+        }
+    }
+
+    /// Cost as (a, b, d) with fuel(dist) = (a * dist^2 + b * dist) / d, if
+    /// it is a polynomial of degree at most two.
+    fn quadratic_form(&self) -> Option<(i64, i64, i64)> {
+        match self {
+            Cost::Linear => Some((0, 1, 1)),
+            Cost::Triangular => Some((1, 1, 2)),
+            Cost::Quadratic => Some((1, 0, 1)),
+            Cost::Table(_) => None,
+        }
+    }
+}
+
+impl FromStr for Cost {
+    type Err = anyhow::Error;
+
+    /// Parses `linear`, `triangular`, `quadratic` or `table:<path>` where the
+    /// file contains comma-separated costs for distance 0, 1, 2, ...
+    fn from_str(s: &str) -> Result<Self> {
+        Ok(match s {
+            "linear" => Cost::Linear,
+            "triangular" => Cost::Triangular,
+            "quadratic" => Cost::Quadratic,
+            _ => {
+                let path =
+                    s.strip_prefix("table:").with_context(|| format!("unknown cost {:?}", s))?;
+                let table = std::fs::read_to_string(path)?;
+                let table: Vec<i64> =
+                    table.trim().split(',').map(|c| c.trim().parse()).try_collect()?;
+                let diffs = table.iter().tuple_windows().map(|(a, b)| b - a).collect_vec();
+                ensure!(
+                    diffs.first().is_none_or(|d| *d >= 0)
+                        && diffs.iter().tuple_windows().all(|(a, b)| a <= b),
+                    "cost table must be nondecreasing and convex"
+                );
+                Cost::Table(table)
+            },
+        })
+    }
+}
+
+fn total_fuel(positions: &[i64], cost: &Cost, a: i64) -> i64 {
+    positions.iter().map(|p| cost.fuel((p - a).abs())).sum()
+}
+
+/// Finds the leftmost position minimizing the total fuel. Since the cost is
+/// convex in the distance the total fuel is convex in the position, so its
+/// slope changes sign exactly once and we can binary search for that point.
+fn optimize(positions: &[i64], cost: &Cost) -> Result<(i64, i64)> {
+    let (min, max) = positions.iter().copied().minmax().into_option().context("no crabs")?;
+    if let Cost::Table(table) = cost {
+        ensure!(table.len() as i64 > max - min, "cost table shorter than {}", max - min + 1);
+    }
+
+    let (mut lo, mut hi) = (min, max);
+    while lo < hi {
+        let mid = lo + (hi - lo) / 2;
+        if total_fuel(positions, cost, mid + 1) - total_fuel(positions, cost, mid) >= 0 {
+            hi = mid;
+        } else {
+            lo = mid + 1;
+        }
+    }
+    Ok((lo, total_fuel(positions, cost, lo)))
+}
+
+/// Total fuel for every position in min..=max of the crabs. Polynomial costs
+/// take O(n + range) using prefix sums over the position histogram, tables
+/// fall back to summing over the distinct positions.
+fn fuel_curve(positions: &[i64], cost: &Cost) -> Result<(i64, Vec<i64>)> {
+    let (min, max) = positions.iter().copied().minmax().into_option().context("no crabs")?;
+    let range = (max - min + 1) as usize;
+    let mut counts = vec![0i64; range];
+    for p in positions {
+        counts[(p - min) as usize] += 1;
+    }
+
+    let Some((a2, a1, den)) = cost.quadratic_form() else {
+        if let Cost::Table(table) = cost {
+            ensure!(table.len() >= range, "cost table shorter than {}", range);
+        }
+        let occupied = counts.iter().enumerate().filter(|(_, c)| **c > 0).collect_vec();
+        let fuel_at = |a: i64| {
+            let terms = occupied.iter().map(|(p, c)| *c * cost.fuel((*p as i64 - a).abs()));
+            terms.sum()
+        };
+        return Ok((min, (0..range as i64).map(fuel_at).collect()));
+    };
+
+    // Positions relative to min, so x ranges over 0..range.
+    let n = positions.len() as i64;
+    let (s1, s2) = counts.iter().enumerate().fold((0, 0), |(s1, s2), (x, c)| {
+        let x = x as i64;
+        (s1 + c * x, s2 + c * x * x)
+    });
+    let (mut left_count, mut left_sum) = (0, 0);
+    let mut curve = Vec::with_capacity(range);
+    for (x, c) in counts.iter().enumerate() {
+        let x = x as i64;
+        let squares = s2 - 2 * x * s1 + x * x * n;
+        let (right_count, right_sum) = (n - left_count, s1 - left_sum);
+        let abs = x * left_count - left_sum + right_sum - x * right_count;
+        curve.push((a2 * squares + a1 * abs) / den);
+        left_count += c;
+        left_sum += c * x;
+    }
+    Ok((min, curve))
+}
+
+fn main() -> Result<()> {
+    let mut costs = Vec::new();
+    let mut csv_path = None;
+    let mut within_pct: Option<f64> = None;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--csv" => csv_path = Some(args.next().context("--csv needs a path")?),
+            "--within" => within_pct = Some(args.next().context("--within needs x")?.parse()?),
+            _ => costs.push((arg.parse::<Cost>()?, arg)),
+        }
+    }
+
+    let input = std::fs::read_to_string("inputs/day07.txt")?;
+    let start = std::time::Instant::now();
+    let positions: Vec<i64> = input.trim().split(',').map(str::parse).try_collect()?;
+
+    if costs.is_empty() {
+        let (part1, part2) =
+            (optimize(&positions, &Cost::Linear)?, optimize(&positions, &Cost::Triangular)?);
+        println!("time: {:?}", start.elapsed());
+        println!("part1: {} (position {})", part1.1, part1.0);
+        println!("part2: {} (position {})", part2.1, part2.0);
+        costs = vec![(Cost::Linear, "linear".into()), (Cost::Triangular, "triangular".into())];
+    } else {
+        for (cost, name) in &costs {
+            let (position, fuel) = optimize(&positions, cost)?;
+            println!("{}: {} (position {})", name, fuel, position);
+        }
+    }
+    if csv_path.is_none() && within_pct.is_none() {
+        return Ok(());
+    }
+
+    let curves: Vec<(i64, Vec<i64>)> =
+        costs.iter().map(|(cost, _)| fuel_curve(&positions, cost)).try_collect()?;
+    if let Some(pct) = within_pct {
+        for ((_, name), (min, curve)) in costs.iter().zip(&curves) {
+            let best = *curve.iter().min().context("empty curve")?;
+            let limit = best as f64 * (1.0 + pct / 100.0);
+            let near = curve.iter().positions(|f| *f as f64 <= limit).map(|i| min + i as i64);
+            let near = near.collect_vec();
+            let span = near.first().zip(near.last()).context("empty curve")?;
+            println!(
+                "{}: {} positions within {}% ({}..={})",
+                name,
+                near.len(),
+                pct,
+                span.0,
+                span.1
+            );
+        }
+    }
+    if let Some(path) = csv_path {
+        let min = curves[0].0;
+        let mut header =
+            std::iter::once("position").chain(costs.iter().map(|(_, name)| name.as_str()));
+        let mut out = header.join(",") + "\n";
+        for i in 0..curves[0].1.len() {
+            let mut fuels = curves.iter().map(|(_, curve)| curve[i]);
+            out += &format!("{},{}\n", min + i as i64, fuels.join(","));
+        }
+        std::fs::write(path, out)?;
+    }
+    Ok(())
+}
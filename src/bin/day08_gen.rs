@@ -0,0 +1,141 @@
+use std::str::FromStr;
+
+use anyhow::{bail, ensure, Context, Result};
+use aoc2021::seven_segment::FONT;
+use itertools::Itertools;
+
+
+/// SplitMix64, small and seedable so generated inputs are reproducible.
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+
+    fn shuffle<T>(&mut self, v: &mut [T]) {
+        for i in (1..v.len()).rev() {
+            v.swap(i, self.below(i + 1));
+        }
+    }
+}
+
+/// Ways to break an entry, one per error the decoders should report.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Corruption {
+    MissingPattern,
+    DuplicatePattern,
+    InconsistentSegments,
+    UnknownDisplayDigit,
+}
+
+impl FromStr for Corruption {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Ok(match s {
+            "missing" => Corruption::MissingPattern,
+            "duplicate" => Corruption::DuplicatePattern,
+            "inconsistent" => Corruption::InconsistentSegments,
+            "unknown-display" => Corruption::UnknownDisplayDigit,
+            _ => bail!("unknown corruption {:?}", s),
+        })
+    }
+}
+
+/// Writes a pattern through the wiring, wiring[s] being the wire that drives
+/// segment s, with its letters in random order.
+fn encode_pattern(segments: u8, wiring: &[u8; 7], rng: &mut Rng) -> String {
+    let mut letters = (0..7)
+        .filter(|s| segments & (1 << s) != 0)
+        .map(|s| (b'a' + wiring[s]) as char)
+        .collect_vec();
+    rng.shuffle(&mut letters);
+    letters.into_iter().collect()
+}
+
+fn encode(number: &str, corruption: Option<Corruption>, rng: &mut Rng) -> Result<String> {
+    let digits: Vec<usize> = number
+        .chars()
+        .map(|c| c.to_digit(10).map(|d| d as usize).context("not a decimal number"))
+        .try_collect()?;
+    let mut wiring = [0, 1, 2, 3, 4, 5, 6];
+    rng.shuffle(&mut wiring);
+
+    let mut patterns = FONT.to_vec();
+    let mut display = digits.iter().map(|d| FONT[*d]).collect_vec();
+    match corruption {
+        None => (),
+        Some(Corruption::MissingPattern) => {
+            patterns.remove(rng.below(10));
+        },
+        Some(Corruption::DuplicatePattern) => {
+            let (i, j) = (rng.below(10), rng.below(9));
+            patterns[i] = patterns[if j < i { j } else { j + 1 }];
+        },
+        Some(Corruption::InconsistentSegments) => {
+            // Move one lit segment of a digit other than 1, 4, 7 and 8 to an
+            // unlit position. The result must not be a digit, and must overlap
+            // with 1 and 4 like the original so it can't be told apart from it
+            // without resolving the wiring.
+            let i = [0, 2, 3, 5, 6, 9][rng.below(6)];
+            let overlaps = |p: u8| [p, p & FONT[1], p & FONT[4]].map(u8::count_ones);
+            let lit = (0..7).filter(|s| patterns[i] & (1 << s) != 0).collect_vec();
+            let unlit = (0..7).filter(|s| patterns[i] & (1 << s) == 0).collect_vec();
+            let mut moved = patterns[i];
+            while FONT.contains(&moved) || overlaps(moved) != overlaps(patterns[i]) {
+                let (l, u) = (lit[rng.below(lit.len())], unlit[rng.below(unlit.len())]);
+                moved = patterns[i] ^ (1 << l) ^ (1 << u);
+            }
+            patterns[i] = moved;
+        },
+        Some(Corruption::UnknownDisplayDigit) => {
+            ensure!(!display.is_empty(), "cannot corrupt an empty display");
+            let i = rng.below(display.len());
+            let mut flip = 1 << rng.below(7);
+            while FONT.contains(&(display[i] ^ flip)) {
+                flip = 1 << rng.below(7);
+            }
+            display[i] ^= flip;
+        },
+    }
+
+    rng.shuffle(&mut patterns);
+    let patterns = patterns.iter().map(|p| encode_pattern(*p, &wiring, rng)).join(" ");
+    let display = display.iter().map(|p| encode_pattern(*p, &wiring, rng)).join(" ");
+    Ok(format!("{} | {}", patterns, display))
+}
+
+fn main() -> Result<()> {
+    let mut seed = 0;
+    let mut count = 200;
+    let mut corruption = None;
+    let mut numbers = Vec::new();
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().with_context(|| format!("{} needs a value", arg));
+        match arg.as_str() {
+            "--seed" => seed = value()?.parse()?,
+            "--count" => count = value()?.parse()?,
+            "--corrupt" => corruption = Some(value()?.parse()?),
+            _ => numbers.push(arg),
+        }
+    }
+
+    let mut rng = Rng(seed);
+    if numbers.is_empty() {
+        numbers = (0..count).map(|_| format!("{:04}", rng.below(10000))).collect();
+    }
+    for number in numbers {
+        println!("{}", encode(&number, corruption, &mut rng)?);
+    }
+    Ok(())
+}
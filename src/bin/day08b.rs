@@ -1,55 +1,133 @@
-use anyhow::{Context, Ok, Result};
+use anyhow::{Context, Result};
+use aoc2021::seven_segment::FONT;
+use derive_more::Display;
+use itertools::Itertools;
 
-fn parse_digit_segments(s: &str) -> u8 {
+
+#[derive(Clone, Copy, Debug, Display, PartialEq, Eq)]
+enum DecodeError {
+    #[display(fmt = "expected 10 patterns, got {}", _0)]
+    PatternCount(usize),
+    #[display(fmt = "pattern {} occurs more than once", "show(*_0)")]
+    DuplicatePattern(u8),
+    #[display(fmt = "no pattern matches digit {}", _0)]
+    MissingDigit(usize),
+    #[display(fmt = "patterns do not form a consistent wiring")]
+    InconsistentSegments,
+    #[display(fmt = "display digit {} is not among the patterns", "show(*_0)")]
+    UnknownDisplayDigit(u8),
+}
+
+impl std::error::Error for DecodeError {}
+
+#[derive(Clone, Copy, Debug)]
+struct Decoded {
+    /// wiring[s] is the scrambled wire driving segment 'a' + s.
+    wiring: [u8; 7],
+    value: usize,
+}
+
+fn parse_digit_segments(s: &str) -> Result<u8> {
     // Parse seven segments into bitset.
-    s.bytes().map(|b| 1 << (b - b'a')).sum()
+    s.bytes().try_fold(0, |acc, b| {
+        anyhow::ensure!((b'a'..=b'g').contains(&b), "invalid segment {:?}", b as char);
+        Ok(acc | 1 << (b - b'a'))
+    })
+}
+
+fn show(digit: u8) -> String {
+    (0..7).filter(|i| digit & (1 << i) != 0).map(|i| (b'a' + i) as char).collect()
 }
 
-fn decode_display<I: IntoIterator<Item = u8> + Clone>(digits: I, display: I) -> usize {
+fn decode_display(digits: &[u8], display: &[u8]) -> Result<Decoded, DecodeError> {
+    if digits.len() != 10 {
+        return Err(DecodeError::PatternCount(digits.len()));
+    }
+    if let Some(dup) = digits.iter().duplicates().next() {
+        return Err(DecodeError::DuplicatePattern(*dup));
+    }
+
     // Decode in two passes, we can directly identify the 1 and 4 using just the
     // number of segments and the rest using the overlap with 1 and 4.
-    let mut decoded = [0; 10];
-    for digit in digits.clone() {
+    let mut decoded = [None; 10];
+    let assign = |decoded: &mut [Option<u8>], d: usize, digit: u8| match decoded[d].replace(digit) {
+        Some(_) => Err(DecodeError::InconsistentSegments),
+        None => Ok(()),
+    };
+    for digit in digits.iter().copied() {
         match digit.count_ones() {
-            2 => decoded[1] = digit,
-            4 => decoded[4] = digit,
-            3 => decoded[7] = digit,
-            7 => decoded[8] = digit,
+            2 => assign(&mut decoded, 1, digit)?,
+            4 => assign(&mut decoded, 4, digit)?,
+            3 => assign(&mut decoded, 7, digit)?,
+            7 => assign(&mut decoded, 8, digit)?,
             _ => (),
         }
     }
 
-    for digit in digits {
-        let one_overlap = (digit & decoded[1]).count_ones();
-        let four_overlap = (digit & decoded[4]).count_ones();
+    let one = decoded[1].ok_or(DecodeError::MissingDigit(1))?;
+    let four = decoded[4].ok_or(DecodeError::MissingDigit(4))?;
+    for digit in digits.iter().copied() {
+        let one_overlap = (digit & one).count_ones();
+        let four_overlap = (digit & four).count_ones();
         match (digit.count_ones(), one_overlap, four_overlap) {
-            (6, 2, 3) => decoded[0] = digit,
-            (5, 1, 2) => decoded[2] = digit,
-            (5, 2, 3) => decoded[3] = digit,
-            (5, 1, 3) => decoded[5] = digit,
-            (6, 1, 3) => decoded[6] = digit,
-            (6, 2, 4) => decoded[9] = digit,
+            (6, 2, 3) => assign(&mut decoded, 0, digit)?,
+            (5, 1, 2) => assign(&mut decoded, 2, digit)?,
+            (5, 2, 3) => assign(&mut decoded, 3, digit)?,
+            (5, 1, 3) => assign(&mut decoded, 5, digit)?,
+            (6, 1, 3) => assign(&mut decoded, 6, digit)?,
+            (6, 2, 4) => assign(&mut decoded, 9, digit)?,
             _ => (),
         }
     }
+    let decoded: Vec<u8> =
+        (0..10).map(|d| decoded[d].ok_or(DecodeError::MissingDigit(d))).try_collect()?;
+
+    // The wire of a segment is lit in exactly the digits that use the segment.
+    let mut wiring = [0; 7];
+    for (s, wire) in wiring.iter_mut().enumerate() {
+        let lit = decoded.iter().zip(FONT).map(|(d, f)| if f & (1 << s) != 0 { *d } else { !d });
+        let candidates = lit.fold(0x7f, |acc, d| acc & d);
+        if candidates.count_ones() != 1 {
+            return Err(DecodeError::InconsistentSegments);
+        }
+        *wire = candidates.trailing_zeros() as u8;
+    }
 
-    display
-        .into_iter()
-        .map(|digit| decoded.iter().position(|d| *d == digit).unwrap())
-        .fold(0, |sum, digit| 10 * sum + digit)
+    let value = display.iter().try_fold(0, |sum, digit| {
+        let d = decoded.iter().position(|d| d == digit);
+        Ok(10 * sum + d.ok_or(DecodeError::UnknownDisplayDigit(*digit))?)
+    })?;
+    Ok(Decoded { wiring, value })
 }
 
 fn main() -> Result<()> {
+    let show_wiring = std::env::args().any(|arg| arg == "--wiring");
     let input = std::fs::read_to_string("inputs/day08.txt")?;
     let start = std::time::Instant::now();
-    let displays = input.lines().map(|line| {
-        let (digits, display) = line.split_once(" | ").context("invalid line")?;
-        let [digits, display] = [digits, display].map(|s| s.split(' ').map(parse_digit_segments));
-        Ok(decode_display(digits, display))
-    });
+    let entries: Vec<Result<Decoded, DecodeError>> = input
+        .lines()
+        .map(|line| {
+            let (digits, display) = line.split_once(" | ").context("invalid line")?;
+            let [digits, display]: [Vec<u8>; 2] = [
+                digits.split(' ').map(parse_digit_segments).try_collect()?,
+                display.split(' ').map(parse_digit_segments).try_collect()?,
+            ];
+            Ok::<_, anyhow::Error>(decode_display(&digits, &display))
+        })
+        .try_collect()?;
 
-    let answer = itertools::process_results(displays, |it| it.sum::<usize>())?;
+    let answer = entries.iter().flatten().map(|e| e.value).sum::<usize>();
     println!("time: {:?}", start.elapsed());
+    for (i, entry) in entries.iter().enumerate() {
+        match entry {
+            Ok(e) if show_wiring => {
+                let wires = e.wiring.iter().map(|w| (b'a' + w) as char).collect::<String>();
+                println!("line {}: {} (wiring abcdefg -> {})", i + 1, e.value, wires);
+            },
+            Ok(_) => (),
+            Err(err) => eprintln!("line {}: {}", i + 1, err),
+        }
+    }
     println!("{}", answer);
     Ok(())
 }
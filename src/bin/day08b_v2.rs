@@ -1,6 +1,7 @@
 use std::collections::HashMap;
 
-use anyhow::{Context, Ok, Result};
+use anyhow::{Context, Result};
+use derive_more::Display;
 use itertools::Itertools;
 
 
@@ -14,26 +15,82 @@ use itertools::Itertools;
    7777    is used, sum the relevant counts for each unknown digit and look up.
 */
 
-fn main() -> Result<()> {
-    #[rustfmt::skip]
-    let signatures = HashMap::from([
-        (42, 0), (17, 1), (34, 2), (39, 3), (30, 4),
-        (37, 5), (41, 6), (25, 7), (49, 8), (45, 9),
-    ]);
+#[rustfmt::skip]
+const SIGNATURES: [(usize, usize); 10] = [
+    (42, 0), (17, 1), (34, 2), (39, 3), (30, 4),
+    (37, 5), (41, 6), (25, 7), (49, 8), (45, 9),
+];
+
+#[derive(Clone, Debug, Display, PartialEq, Eq)]
+enum DecodeError {
+    #[display(fmt = "expected 10 patterns, got {}", _0)]
+    PatternCount(usize),
+    #[display(fmt = "pattern {} occurs more than once", _0)]
+    DuplicatePattern(String),
+    #[display(fmt = "segment usage counts {:?} do not match a display", _0)]
+    SegmentCounts(Vec<usize>),
+    #[display(fmt = "patterns do not form a consistent wiring")]
+    InconsistentSegments,
+    #[display(fmt = "display digit {} is not among the patterns", _0)]
+    UnknownDisplayDigit(String),
+}
+
+impl std::error::Error for DecodeError {}
+
+fn decode(unique: &[&str], display: &[&str]) -> Result<usize, DecodeError> {
+    if unique.len() != 10 {
+        return Err(DecodeError::PatternCount(unique.len()));
+    }
+    let sorted = |p: &str| p.bytes().sorted().map(char::from).collect::<String>();
+    if let Some(dup) = unique.iter().map(|p| sorted(p)).duplicates().next() {
+        return Err(DecodeError::DuplicatePattern(dup));
+    }
 
+    // The signature lookup is only meaningful for a valid set of patterns,
+    // so check that the segments are used as often as on a real display and
+    // that every digit gets its own signature.
+    let unique_counts = unique.iter().flat_map(|p| p.bytes()).counts();
+    let usage = unique_counts.values().copied().sorted().collect_vec();
+    if usage != [4, 6, 7, 7, 8, 8, 9] {
+        return Err(DecodeError::SegmentCounts(usage));
+    }
+    let signatures = HashMap::from(SIGNATURES);
+    let signature = |digit: &str| digit.bytes().map(|b| unique_counts[&b]).sum::<usize>();
+    let digits: HashMap<String, usize> = unique
+        .iter()
+        .map(|p| Some((sorted(p), *signatures.get(&signature(p))?)))
+        .collect::<Option<_>>()
+        .ok_or(DecodeError::InconsistentSegments)?;
+    if digits.values().unique().count() != 10 {
+        return Err(DecodeError::InconsistentSegments);
+    }
+
+    display.iter().try_fold(0, |sum, digit| {
+        let d = digits.get(&sorted(digit));
+        Ok(10 * sum + d.ok_or_else(|| DecodeError::UnknownDisplayDigit(digit.to_string()))?)
+    })
+}
+
+fn main() -> Result<()> {
     let input = std::fs::read_to_string("inputs/day08.txt")?;
     let start = std::time::Instant::now();
-    let displays = input.lines().map(|line| {
-        let (unique, display) = line.split_once(" | ").context("invalid line")?;
-        let unique_counts = unique.bytes().counts();
-        Ok(display
-            .split(' ')
-            .map(|digit| signatures[&digit.bytes().map(|b| unique_counts[&b]).sum::<usize>()])
-            .fold(0, |sum, digit| 10 * sum + digit))
-    });
-
-    let answer = itertools::process_results(displays, |it| it.sum::<usize>())?;
+    let displays: Vec<Result<usize, DecodeError>> = input
+        .lines()
+        .map(|line| {
+            let (unique, display) = line.split_once(" | ").context("invalid line")?;
+            let [unique, display] = [unique, display].map(|s| s.split(' ').collect_vec());
+            Ok::<_, anyhow::Error>(decode(&unique, &display))
+        })
+        .try_collect()?;
+
+    // A bad entry only loses its own value.
+    let answer = displays.iter().flatten().sum::<usize>();
     println!("time: {:?}", start.elapsed());
+    for (i, display) in displays.iter().enumerate() {
+        if let Err(err) = display {
+            eprintln!("line {}: {}", i + 1, err);
+        }
+    }
     println!("{}", answer);
     Ok(())
 }
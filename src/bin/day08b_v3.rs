@@ -0,0 +1,231 @@
+use anyhow::{bail, ensure, Context, Result};
+use derive_more::Display;
+use itertools::Itertools;
+
+
+/*
+    Instead of hand-derived overlaps this decoder takes the font as data: a
+    symbol and its set of lit segments per glyph. The wiring is found by
+    assigning each scrambled pattern to a glyph of the same size, narrowing
+    down which segments every wire could drive as we go and backtracking as
+    soon as a wire runs out of candidates. This works for 14/16-segment
+    displays or custom glyphs as long as at most 32 segments are used.
+*/
+
+const STANDARD_FONT: &str =
+    "0 abcefg\n1 cf\n2 acdeg\n3 acdfg\n4 bcdf\n5 abdfg\n6 abdefg\n7 acf\n8 abcdefg\n9 abcdfg";
+
+struct Font {
+    num_segments: usize,
+    symbols: Vec<char>,
+    glyphs: Vec<u32>,
+}
+
+#[derive(Clone, Copy, Debug, Display, PartialEq, Eq)]
+enum DecodeError {
+    #[display(fmt = "expected {} patterns, got {}", _0, _1)]
+    PatternCount(usize, usize),
+    #[display(fmt = "no wiring maps the patterns onto the font")]
+    NoWiring,
+    #[display(fmt = "multiple wirings map the patterns onto the font")]
+    Ambiguous,
+    #[display(fmt = "display digit {} is not among the patterns", "show(*_0)")]
+    UnknownDisplayDigit(u32),
+}
+
+impl std::error::Error for DecodeError {}
+
+fn parse_segments(s: &str) -> Result<u32> {
+    s.bytes().try_fold(0, |acc, b| {
+        ensure!(b.is_ascii_lowercase() && b - b'a' < 32, "invalid segment {:?}", b as char);
+        Ok(acc | 1 << (b - b'a'))
+    })
+}
+
+/// Inverse of `parse_segments`.
+fn show(segments: u32) -> String {
+    (0..32)
+        .filter(|s| segments & (1 << s) != 0)
+        .map(|s| (b'a' + s) as char)
+        .collect()
+}
+
+impl Font {
+    fn parse(s: &str) -> Result<Self> {
+        let mut symbols = Vec::new();
+        let mut glyphs = Vec::new();
+        for line in s.lines().filter(|l| !l.trim().is_empty()) {
+            let (symbol, segments) =
+                line.trim().split_once(' ').context("expected symbol and segments")?;
+            ensure!(symbol.chars().count() == 1, "symbol {:?} is not a single character", symbol);
+            symbols.extend(symbol.chars());
+            glyphs.push(parse_segments(segments.trim())?);
+        }
+        ensure!(glyphs.iter().all_unique(), "font contains identical glyphs");
+        let all = glyphs.iter().fold(0, |acc, g| acc | g);
+        let num_segments = 32 - all.leading_zeros() as usize;
+        ensure!(num_segments > 0, "font has no segments");
+        Ok(Font {
+            num_segments,
+            symbols,
+            glyphs,
+        })
+    }
+
+    /// Groups of symbols that share the segment-usage signature of day08b_v2.
+    /// If there are any the signature lookup cannot decode this font.
+    fn signature_collisions(&self) -> Vec<Vec<char>> {
+        let usage = (0..self.num_segments)
+            .map(|s| self.glyphs.iter().filter(|g| *g & (1 << s) != 0).count())
+            .collect_vec();
+        let signature = |g: u32| {
+            (0..self.num_segments)
+                .filter(|s| g & (1 << s) != 0)
+                .map(|s| usage[s])
+                .sum::<usize>()
+        };
+        let groups =
+            self.symbols.iter().zip(&self.glyphs).into_group_map_by(|(_, g)| signature(**g));
+        groups
+            .into_values()
+            .filter(|v| v.len() > 1)
+            .map(|v| v.into_iter().map(|(c, _)| *c).collect())
+            .collect()
+    }
+}
+
+/// Assigns patterns[i..] to unused glyphs, narrowing candidates[w] (the
+/// segments wire w could drive). Stops after finding two wirings.
+fn search(
+    font: &Font,
+    patterns: &[u32],
+    i: usize,
+    used: &mut [bool],
+    candidates: &[u32],
+    found: &mut Vec<Vec<u32>>,
+) {
+    if found.len() >= 2 {
+        return;
+    }
+    if i == patterns.len() {
+        found.push(candidates.to_vec());
+        return;
+    }
+
+    let wires = font.num_segments;
+    for g in 0..font.glyphs.len() {
+        let glyph = font.glyphs[g];
+        if used[g] || glyph.count_ones() != patterns[i].count_ones() {
+            continue;
+        }
+        let lit = |w: usize| patterns[i] & (1 << w) != 0;
+        let narrowed = (0..wires).map(|w| candidates[w] & if lit(w) { glyph } else { !glyph });
+        let narrowed = narrowed.collect_vec();
+        if narrowed.contains(&0) {
+            continue;
+        }
+        used[g] = true;
+        search(font, patterns, i + 1, used, &narrowed, found);
+        used[g] = false;
+    }
+}
+
+/// Returns the decoded display and the wiring, wiring[w] being the segment
+/// driven by wire w.
+fn decode(
+    font: &Font,
+    patterns: &[u32],
+    display: &[u32],
+) -> Result<(String, Vec<usize>), DecodeError> {
+    if patterns.len() != font.glyphs.len() {
+        return Err(DecodeError::PatternCount(font.glyphs.len(), patterns.len()));
+    }
+
+    let all_segments = u32::MAX >> (32 - font.num_segments);
+    if patterns.iter().chain(display).any(|p| p & !all_segments != 0) {
+        return Err(DecodeError::NoWiring);
+    }
+    let mut found = Vec::new();
+    let mut used = vec![false; font.glyphs.len()];
+    let candidates = vec![all_segments; font.num_segments];
+    search(font, patterns, 0, &mut used, &candidates, &mut found);
+
+    // Every glyph is accounted for, so a wire that could still drive several
+    // segments means those segments are indistinguishable.
+    let wiring = match found.as_slice() {
+        [] => return Err(DecodeError::NoWiring),
+        [c] if c.iter().all(|s| s.count_ones() == 1) => {
+            c.iter().map(|s| s.trailing_zeros() as usize).collect_vec()
+        },
+        _ => return Err(DecodeError::Ambiguous),
+    };
+    if !wiring.iter().all_unique() {
+        return Err(DecodeError::NoWiring);
+    }
+
+    let rewire = |p: u32| {
+        (0..font.num_segments)
+            .filter(|w| p & (1 << w) != 0)
+            .fold(0, |acc, w| acc | 1 << wiring[w])
+    };
+    let value = display
+        .iter()
+        .map(|p| {
+            let g = font.glyphs.iter().position(|g| *g == rewire(*p));
+            Ok(font.symbols[g.ok_or(DecodeError::UnknownDisplayDigit(*p))?])
+        })
+        .try_collect()?;
+    Ok((value, wiring))
+}
+
+fn main() -> Result<()> {
+    let mut font_src = STANDARD_FONT.to_string();
+    let mut input_path = "inputs/day08.txt".to_string();
+    let mut verbose = false;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--font" => {
+                font_src = std::fs::read_to_string(args.next().context("--font needs a path")?)?
+            },
+            "--input" => input_path = args.next().context("--input needs a path")?,
+            "--verbose" => verbose = true,
+            _ => bail!("unknown argument {:?}", arg),
+        }
+    }
+    let font = Font::parse(&font_src)?;
+    for group in font.signature_collisions() {
+        eprintln!("warning: symbols {:?} share a segment-usage signature", group);
+    }
+
+    let input = std::fs::read_to_string(input_path)?;
+    let start = std::time::Instant::now();
+    let mut total = 0;
+    let mut report = Vec::new();
+    for (i, line) in input.lines().enumerate() {
+        let (patterns, display) = line.split_once(" | ").context("invalid line")?;
+        let patterns: Vec<u32> = patterns.split(' ').map(parse_segments).try_collect()?;
+        let display: Vec<u32> = display.split(' ').map(parse_segments).try_collect()?;
+        match decode(&font, &patterns, &display) {
+            Ok((value, wiring)) => {
+                match value.parse::<u64>() {
+                    Ok(n) => total += n,
+                    Err(_) => eprintln!(
+                        "line {}: {:?} is not a number, left out of the total",
+                        i + 1,
+                        value
+                    ),
+                }
+                let wiring = wiring.iter().map(|s| (b'a' + *s as u8) as char).collect::<String>();
+                report.push(format!("line {}: {} (wiring {})", i + 1, value, wiring));
+            },
+            Err(err) => eprintln!("line {}: {}", i + 1, err),
+        }
+    }
+    println!("time: {:?}", start.elapsed());
+    if verbose {
+        report.iter().for_each(|line| println!("{}", line));
+    }
+    println!("{}", total);
+    Ok(())
+}
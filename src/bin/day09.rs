@@ -1,40 +1,204 @@
-use anyhow::{Context, Result};
+use anyhow::{bail, ensure, Context, Result};
 use itertools::Itertools;
 
-/// Returns the size and lowest point of the basin at (x, y) if there is any.
-/// Removes the basin in the process.
-fn extract_basin(x: i64, y: i64, w: i64, h: i64, heights: &mut [u8]) -> Option<(u64, u8)> {
-    let in_bounds = (0..w).contains(&x) && (0..h).contains(&y);
-    (in_bounds && heights[(y * w + x) as usize] < 9).then(|| {
-        let mut size = 1;
-        let mut lowest = std::mem::replace(&mut heights[(y * w + x) as usize], 9);
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Basin {
+    size: u64,
+    lowest: u8,
+    low_point: (i64, i64),
+    /// Inclusive bounding box as (min, max) corners.
+    bounds: ((i64, i64), (i64, i64)),
+}
+
+/// Returns the basin at (x, y) if there is any, writing `id` into its cells
+/// in `labels`. Removes the basin in the process. Uses an explicit stack so
+/// huge open basins can't overflow the call stack.
+fn extract_basin(
+    (x, y): (i64, i64),
+    (w, h): (i64, i64),
+    id: u32,
+    heights: &mut [u8],
+    labels: &mut [Option<u32>],
+) -> Option<Basin> {
+    let mut take = |x: i64, y: i64| {
+        let in_bounds = (0..w).contains(&x) && (0..h).contains(&y);
+        let i = (y * w + x) as usize;
+        (in_bounds && heights[i] < 9).then(|| {
+            labels[i] = Some(id);
+            std::mem::replace(&mut heights[i], 9)
+        })
+    };
+
+    let lowest = take(x, y)?;
+    let mut basin = Basin {
+        size: 1,
+        lowest,
+        low_point: (x, y),
+        bounds: ((x, y), (x, y)),
+    };
+    let mut to_visit = vec![(x, y)];
+    while let Some((x, y)) = to_visit.pop() {
         for (nx, ny) in [(x - 1, y), (x + 1, y), (x, y - 1), (x, y + 1)] {
-            if let Some((nsize, nlowest)) = extract_basin(nx, ny, w, h, heights) {
-                size += nsize;
-                lowest = lowest.min(nlowest);
+            if let Some(height) = take(nx, ny) {
+                basin.size += 1;
+                if height < basin.lowest {
+                    basin.lowest = height;
+                    basin.low_point = (nx, ny);
+                }
+                let (lo, hi) = &mut basin.bounds;
+                *lo = (lo.0.min(nx), lo.1.min(ny));
+                *hi = (hi.0.max(nx), hi.1.max(ny));
+                to_visit.push((nx, ny));
             }
         }
-        (size, lowest)
-    })
+    }
+    Some(basin)
+}
+
+/// Finds all basins, labeling each cell with the index of its basin.
+fn find_basins(w: i64, h: i64, heights: &mut [u8]) -> (Vec<Basin>, Vec<Option<u32>>) {
+    let mut labels = vec![None; heights.len()];
+    let mut basins = Vec::new();
+    for (x, y) in itertools::iproduct!(0..w, 0..h) {
+        let id = basins.len() as u32;
+        basins.extend(extract_basin((x, y), (w, h), id, heights, &mut labels));
+    }
+    (basins, labels)
+}
+
+/// Labeled map with one character per basin (cycling through 62 symbols)
+/// and '.' for the ridges between them.
+fn map_to_text(w: i64, labels: &[Option<u32>]) -> String {
+    let symbols = b"0123456789abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ";
+    let cell = |l: &Option<u32>| l.map_or('.', |id| symbols[id as usize % symbols.len()] as char);
+    labels
+        .chunks(w as usize)
+        .map(|row| row.iter().map(cell).join("") + "\n")
+        .collect()
+}
+
+/// Labeled map as a binary PPM, each basin in a pseudo-random color.
+fn map_to_ppm(w: i64, labels: &[Option<u32>]) -> Vec<u8> {
+    let h = labels.len() / w as usize;
+    let mut out = format!("P6\n{} {}\n255\n", w, h).into_bytes();
+    for label in labels {
+        let rgb = label.map_or(0, |id| (id + 1).wrapping_mul(0x9e3779b9) | 0x404040);
+        out.extend(&rgb.to_be_bytes()[1..]);
+    }
+    out
+}
+
+/// Worst-case map of n by n for the extraction: one open basin of zeros.
+fn open_map(n: i64) -> Vec<u8> {
+    vec![0; (n * n) as usize]
+}
+
+/// Worst-case map of n by n for the extraction: a single basin winding
+/// through walls of nines. Every odd row is a wall except for one gap
+/// alternating between ends.
+fn snake_map(n: i64) -> Vec<u8> {
+    let mut snake = vec![0; (n * n) as usize];
+    for y in (1..n).step_by(2) {
+        let gap = if y % 4 == 1 { n - 1 } else { 0 };
+        for x in (0..n).filter(|x| *x != gap) {
+            snake[(y * n + x) as usize] = 9;
+        }
+    }
+    snake
+}
+
+/// Times the extraction on the worst-case maps of n by n.
+fn stress(n: i64) {
+    for (name, mut heights) in [("open", open_map(n)), ("snake", snake_map(n))] {
+        let start = std::time::Instant::now();
+        let num_basins = find_basins(n, n, &mut heights).0.len();
+        println!("{} {}x{}: {} basins in {:?}", name, n, n, num_basins, start.elapsed());
+    }
 }
 
 fn main() -> Result<()> {
+    let mut top_k = 3;
+    let mut report = false;
+    let mut map_path = None;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().with_context(|| format!("{} needs a value", arg));
+        match arg.as_str() {
+            "--stress" => {
+                stress(value()?.parse()?);
+                return Ok(());
+            },
+            "--top" => top_k = value()?.parse()?,
+            "--report" => report = true,
+            "--map" => map_path = Some(value()?),
+            _ => bail!("unknown argument {:?}", arg),
+        }
+    }
+
     let input = std::fs::read_to_string("inputs/day09.txt")?;
     let start = std::time::Instant::now();
     let w = input.split_once('\n').context("no newline")?.0.trim().len() as i64;
     let h = input.trim().lines().count() as i64;
     let mut heights = input.lines().flat_map(|l| l.bytes().map(|b| b - b'0')).collect_vec();
 
-    let mut basins = itertools::iproduct!(0..w, 0..h)
-        .flat_map(|(x, y)| extract_basin(x, y, w, h, &mut heights))
-        .collect_vec();
-    let num_basins = basins.len();
-
-    let part1: u64 = basins.iter().map(|(_size, lowest)| (1 + lowest) as u64).sum();
-    let largest_basins = basins.select_nth_unstable(num_basins.saturating_sub(4)).2;
-    let part2: Option<u64> = largest_basins.iter().map(|(size, _lowest)| *size).product1();
+    let (basins, labels) = find_basins(w, h, &mut heights);
+    let part1: u64 = basins.iter().map(|b| (1 + b.lowest) as u64).sum();
+    let mut by_size = basins.iter().collect_vec();
+    by_size.sort_by_key(|b| std::cmp::Reverse(b.size));
+    ensure!(by_size.len() >= top_k, "fewer than {} basins found", top_k);
+    let part2 = by_size[..top_k]
+        .iter()
+        .try_fold(1u64, |acc, b| acc.checked_mul(b.size))
+        .context("product of basin sizes overflows")?;
     println!("time: {:?}", start.elapsed());
     println!("part1: {}", part1);
-    println!("part2: {}", part2.context("no basins found")?);
+    println!("part2: {}", part2);
+
+    if report {
+        for (id, b) in basins.iter().enumerate() {
+            let ((x0, y0), (x1, y1)) = b.bounds;
+            println!(
+                "basin {}: size {}, low point {:?} height {}, bounds ({}, {})..=({}, {})",
+                id, b.size, b.low_point, b.lowest, x0, y0, x1, y1
+            );
+        }
+    }
+    match map_path {
+        Some(path) if path.ends_with(".ppm") => std::fs::write(path, map_to_ppm(w, &labels))?,
+        Some(path) => std::fs::write(path, map_to_text(w, &labels))?,
+        None => (),
+    }
     Ok(())
 }
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn summary(n: i64, mut heights: Vec<u8>) -> Vec<(u64, u8)> {
+        let basins = find_basins(n, n, &mut heights).0;
+        basins.iter().map(|b| (b.size, b.lowest)).collect()
+    }
+
+    #[test]
+    fn open_map_is_one_basin() {
+        assert_eq!(summary(300, open_map(300)), [(300 * 300, 0)]);
+    }
+
+    #[test]
+    fn snake_map_is_one_basin() {
+        let snake = snake_map(301);
+        let expected = snake.iter().filter(|h| **h < 9).count() as u64;
+        assert_eq!(summary(301, snake), [(expected, 0)]);
+    }
+
+    #[test]
+    #[ignore = "slow in debug builds"]
+    fn large_maps_are_one_basin() {
+        assert_eq!(summary(2000, open_map(2000)), [(2000 * 2000, 0)]);
+        let snake = snake_map(2000);
+        let expected = snake.iter().filter(|h| **h < 9).count() as u64;
+        assert_eq!(summary(2000, snake), [(expected, 0)]);
+    }
+}
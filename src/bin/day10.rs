@@ -2,38 +2,213 @@ use std::collections::HashMap;
 use std::fs::File;
 use std::io::{BufRead, BufReader};
 
-use anyhow::Result;
+use anyhow::{bail, ensure, Context, Result};
 
-fn main() -> Result<()> {
-    let input = BufReader::new(File::open("inputs/day10.txt")?);
-    let start = std::time::Instant::now();
-    let illegal_points = HashMap::from([(b')', 3u64), (b']', 57), (b'}', 1197), (b'>', 25137)]);
-    let closer_points = HashMap::from([(b'(', 1u64), (b'[', 2), (b'{', 3), (b'<', 4)]);
-    let openers = HashMap::from([(b')', b'('), (b']', b'['), (b'}', b'{'), (b'>', b'<')]);
+/// Delimiter pairs with their scores: points for finding the closer where it
+/// is illegal, and the per-character points of completion strings, which are
+/// combined in base `completion_base`.
+struct Syntax {
+    openers: HashMap<u8, (u8, u64)>,
+    closers: HashMap<u8, (u8, u64)>,
+    completion_base: u64,
+}
 
-    let mut total_illegal_score = 0;
-    let mut incomplete_scores = Vec::new();
-    for line in input.lines() {
+/// Outcome of checking one line, columns being 0-based byte offsets.
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum LineStatus {
+    Valid,
+    /// `expected` is the closer of the innermost open chunk, if any.
+    Corrupted {
+        col: usize,
+        expected: Option<u8>,
+        found: u8,
+    },
+    /// `completion` is the sequence of closers that completes the line.
+    Incomplete {
+        completion: Vec<u8>,
+    },
+    InvalidByte {
+        col: usize,
+        byte: u8,
+    },
+}
+
+impl Syntax {
+    fn standard() -> Self {
+        let pairs = [
+            (b'(', b')', 3, 1),
+            (b'[', b']', 57, 2),
+            (b'{', b'}', 1197, 3),
+            (b'<', b'>', 25137, 4),
+        ];
+        Self::new(&pairs, 5).unwrap()
+    }
+
+    fn new(pairs: &[(u8, u8, u64, u64)], completion_base: u64) -> Result<Self> {
+        let mut openers = HashMap::new();
+        let mut closers = HashMap::new();
+        for &(open, close, illegal_points, closer_points) in pairs {
+            let fresh = !openers.contains_key(&open) && !closers.contains_key(&open);
+            ensure!(fresh && open != close, "delimiter {:?} used twice", open as char);
+            ensure!(!openers.contains_key(&close), "delimiter {:?} used twice", close as char);
+            ensure!(
+                closers.insert(close, (open, illegal_points)).is_none(),
+                "delimiter {:?} used twice",
+                close as char
+            );
+            openers.insert(open, (close, closer_points));
+        }
+        Ok(Self {
+            openers,
+            closers,
+            completion_base,
+        })
+    }
+
+    /// Parses one pair per line as `<open><close> <illegal points> <closer
+    /// points>`, plus an optional `base <n>` line.
+    fn parse(s: &str) -> Result<Self> {
+        let mut pairs = Vec::new();
+        let mut base = 5;
+        for line in s.lines().map(str::trim).filter(|l| !l.is_empty()) {
+            let fields: Vec<&str> = line.split_ascii_whitespace().collect();
+            match fields.as_slice() {
+                ["base", n] => base = n.parse()?,
+                [delims, illegal, closer] if delims.len() == 2 => {
+                    let d = delims.as_bytes();
+                    pairs.push((d[0], d[1], illegal.parse()?, closer.parse()?));
+                },
+                _ => bail!("invalid syntax line {:?}", line),
+            }
+        }
+        Self::new(&pairs, base)
+    }
+
+    fn check_line(&self, line: &str) -> LineStatus {
+        // We push the expected closers rather than the openers, so the stack
+        // reversed is the completion string.
         let mut stack = Vec::new();
-        for b in line?.bytes() {
-            if let Some(&opener) = openers.get(&b) {
-                if stack.pop() != Some(opener) {
-                    total_illegal_score += illegal_points[&b];
-                    stack.clear();
-                    break;
+        for (col, b) in line.bytes().enumerate() {
+            if self.closers.contains_key(&b) {
+                let expected = stack.pop();
+                if expected != Some(b) {
+                    return LineStatus::Corrupted {
+                        col,
+                        expected,
+                        found: b,
+                    };
                 }
+            } else if let Some(&(closer, _)) = self.openers.get(&b) {
+                stack.push(closer);
             } else {
-                stack.push(b);
+                return LineStatus::InvalidByte { col, byte: b };
             }
         }
 
-        if !stack.is_empty() {
-            let score = stack.into_iter().rev().fold(0, |tot, b| 5 * tot + closer_points[&b]);
-            incomplete_scores.push(score);
+        stack.reverse();
+        if stack.is_empty() {
+            LineStatus::Valid
+        } else {
+            LineStatus::Incomplete { completion: stack }
+        }
+    }
+
+    fn illegal_score(&self, closer: u8) -> u64 {
+        self.closers[&closer].1
+    }
+
+    fn completion_score(&self, completion: &[u8]) -> u64 {
+        let closer_points = |b: &u8| self.openers[&self.closers[b].0].1;
+        completion
+            .iter()
+            .map(closer_points)
+            .fold(0, |tot, p| self.completion_base * tot + p)
+    }
+}
+
+/// Linter-style report of a line with problems: the message followed by the
+/// line itself and a caret under the offending column.
+fn annotate(line_nr: usize, line: &str, status: &LineStatus) -> Option<String> {
+    let show = |b: u8| format!("{:?}", b as char);
+    let (col, msg) = match status {
+        LineStatus::Valid => return None,
+        LineStatus::Corrupted {
+            col,
+            expected: Some(e),
+            found,
+        } => (*col, format!("expected {}, found {}", show(*e), show(*found))),
+        LineStatus::Corrupted {
+            col,
+            expected: None,
+            found,
+        } => (*col, format!("unmatched closer {}", show(*found))),
+        LineStatus::Incomplete { completion } => {
+            let completion = String::from_utf8_lossy(completion);
+            (line.len(), format!("incomplete, missing {:?}", completion))
+        },
+        LineStatus::InvalidByte { col, byte } => (*col, format!("unexpected byte {}", show(*byte))),
+    };
+    Some(format!(
+        "{}:{}: {}\n    {}\n    {:>width$}",
+        line_nr,
+        col + 1,
+        msg,
+        line,
+        "^",
+        width = col + 1
+    ))
+}
+
+fn main() -> Result<()> {
+    let mut syntax = Syntax::standard();
+    let mut input_path = "inputs/day10.txt".to_string();
+    let mut lint = false;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().with_context(|| format!("{} needs a value", arg));
+        match arg.as_str() {
+            "--syntax" => syntax = Syntax::parse(&std::fs::read_to_string(value()?)?)?,
+            "--input" => input_path = value()?,
+            "--lint" => lint = true,
+            _ => bail!("unknown argument {:?}", arg),
+        }
+    }
+
+    let input = BufReader::new(File::open(&input_path)?);
+    if lint {
+        let mut num_problems = 0;
+        for (i, line) in input.lines().enumerate() {
+            let line = line?;
+            if let Some(report) = annotate(i + 1, &line, &syntax.check_line(&line)) {
+                println!("{}:{}", input_path, report);
+                num_problems += 1;
+            }
+        }
+        ensure!(num_problems == 0, "{} lines with problems", num_problems);
+        return Ok(());
+    }
+
+    let start = std::time::Instant::now();
+    let mut total_illegal_score = 0;
+    let mut incomplete_scores = Vec::new();
+    for (i, line) in input.lines().enumerate() {
+        let line = line?;
+        match syntax.check_line(&line) {
+            LineStatus::Valid => (),
+            LineStatus::Corrupted { found, .. } => {
+                total_illegal_score += syntax.illegal_score(found)
+            },
+            LineStatus::Incomplete { completion } => {
+                incomplete_scores.push(syntax.completion_score(&completion))
+            },
+            status @ LineStatus::InvalidByte { .. } => {
+                eprintln!("{}", annotate(i + 1, &line, &status).unwrap());
+            },
         }
     }
 
     let num_incomplete = incomplete_scores.len();
+    ensure!(num_incomplete > 0, "no incomplete lines");
     let part2 = incomplete_scores.select_nth_unstable(num_incomplete / 2).1;
     println!("time: {:?}", start.elapsed());
     println!("part1: {}", total_illegal_score);
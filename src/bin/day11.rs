@@ -1,46 +1,286 @@
-use anyhow::{Context, Result};
-use hashbrown::HashSet;
+use std::str::FromStr;
+
+use anyhow::{bail, ensure, Context, Result};
+use hashbrown::{HashMap, HashSet};
 use itertools::Itertools;
 
-fn main() -> Result<()> {
-    let input = std::fs::read_to_string("inputs/day11.txt")?;
-    let start = std::time::Instant::now();
-    let width = input.lines().next().context("no lines")?.len() as isize;
-    let mut energy_levels = input
-        .lines()
-        .flat_map(|line| line.trim().bytes().map(|b| b - b'0'))
-        .collect_vec();
-    let height = energy_levels.len() as isize / width;
 
-    let mut total100 = 0;
-    let mut flashed = HashSet::new();
-    let mut step = 0usize;
-    while flashed.len() as isize != width * height {
-        if step <= 100 {
-            total100 += flashed.len();
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Neighborhood {
+    /// Orthogonal neighbors only.
+    Four,
+    /// Orthogonal and diagonal neighbors.
+    Eight,
+    /// Like `Eight`, wrapping around the edges.
+    Torus,
+}
+
+impl Neighborhood {
+    fn offsets(self) -> &'static [(isize, isize)] {
+        #[rustfmt::skip]
+        let offsets: &[(isize, isize)] = match self {
+            Neighborhood::Four => &[(0, -1), (-1, 0), (1, 0), (0, 1)],
+            Neighborhood::Eight | Neighborhood::Torus => &[(-1, -1), (0, -1), (1, -1),
+                                                           (-1, 0),           (1, 0),
+                                                           (-1, 1),  (0, 1),  (1, 1)],
+        };
+        offsets
+    }
+
+    fn neighbor(
+        self,
+        (w, h): (isize, isize),
+        (x, y): (isize, isize),
+        (dx, dy): (isize, isize),
+    ) -> Option<(isize, isize)> {
+        let (nx, ny) = (x + dx, y + dy);
+        match self {
+            Neighborhood::Torus => Some((nx.rem_euclid(w), ny.rem_euclid(h))),
+            _ => ((0..w).contains(&nx) && (0..h).contains(&ny)).then_some((nx, ny)),
+        }
+    }
+}
+
+impl FromStr for Neighborhood {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Ok(match s {
+            "4" => Neighborhood::Four,
+            "8" => Neighborhood::Eight,
+            "torus" => Neighborhood::Torus,
+            _ => bail!("unknown neighborhood {:?}", s),
+        })
+    }
+}
+
+struct Octopuses {
+    width: isize,
+    height: isize,
+    energy_levels: Vec<u8>,
+    neighborhood: Neighborhood,
+    /// An octopus flashes once its energy level exceeds this.
+    threshold: u8,
+    /// Scratch space reused between steps: one bit per octopus that flashed
+    /// and the octopuses waiting to flash.
+    flashed: Vec<u64>,
+    queue: Vec<usize>,
+}
+
+impl Octopuses {
+    fn neighbors(&self, x: isize, y: isize) -> Vec<(isize, isize)> {
+        let (w, h) = (self.width, self.height);
+        let neighbor = |&(dx, dy)| self.neighborhood.neighbor((w, h), (x, y), (dx, dy));
+        self.neighborhood.offsets().iter().filter_map(neighbor).collect()
+    }
+
+    fn new(
+        width: isize,
+        energy_levels: Vec<u8>,
+        neighborhood: Neighborhood,
+        threshold: u8,
+    ) -> Self {
+        let n = energy_levels.len();
+        Octopuses {
+            width,
+            height: n as isize / width,
+            energy_levels,
+            neighborhood,
+            threshold,
+            flashed: vec![0; n.div_ceil(64)],
+            queue: Vec::with_capacity(n),
         }
-        flashed.clear();
+    }
 
-        step += 1;
-        let mut to_increase = itertools::iproduct!(0..width, 0..height).collect_vec();
+    /// Advances one step, returning the number of flashes. Only octopuses
+    /// that reach the threshold enter the queue, and each does so once: when
+    /// its level first exceeds the threshold. Flashed octopuses are never
+    /// increased, so those are exactly the octopuses that flash.
+    fn step(&mut self) -> usize {
+        let (w, h) = (self.width, self.height);
+        for (i, level) in self.energy_levels.iter_mut().enumerate() {
+            *level += 1;
+            if *level > self.threshold {
+                self.queue.push(i);
+            }
+        }
+
+        let mut num_flashes = 0;
+        while let Some(i) = self.queue.pop() {
+            self.flashed[i / 64] |= 1 << (i % 64);
+            self.energy_levels[i] = 0;
+            num_flashes += 1;
+            let (x, y) = (i as isize % w, i as isize / w);
+            for offset in self.neighborhood.offsets() {
+                let Some((nx, ny)) = self.neighborhood.neighbor((w, h), (x, y), *offset) else {
+                    continue;
+                };
+                let j = (ny * w + nx) as usize;
+                if self.flashed[j / 64] & (1 << (j % 64)) == 0 {
+                    self.energy_levels[j] += 1;
+                    if self.energy_levels[j] == self.threshold + 1 {
+                        self.queue.push(j);
+                    }
+                }
+            }
+        }
+        self.flashed.fill(0);
+        num_flashes
+    }
+
+    /// Straightforward step allocating its work list and flashed set, kept
+    /// as reference for `step`.
+    fn step_reference(&mut self) -> usize {
+        let mut flashed = HashSet::new();
+        let mut to_increase = itertools::iproduct!(0..self.width, 0..self.height).collect_vec();
         while let Some((x, y)) = to_increase.pop() {
-            let i = (y * width + x) as usize;
-            if !(x < 0 || x >= width || y < 0 || y >= height || flashed.contains(&i)) {
-                energy_levels[i] += 1;
-                if energy_levels[i] > 9 {
-                    energy_levels[i] = 0;
+            let i = (y * self.width + x) as usize;
+            if !flashed.contains(&i) {
+                self.energy_levels[i] += 1;
+                if self.energy_levels[i] > self.threshold {
+                    self.energy_levels[i] = 0;
                     flashed.insert(i);
-                    #[rustfmt::skip]
-                    to_increase.extend([(x - 1, y - 1), (x, y - 1), (x + 1, y - 1),
-                                        (x - 1, y),                 (x + 1, y),
-                                        (x - 1, y + 1), (x, y + 1), (x + 1, y + 1)]);
+                    to_increase.extend(self.neighbors(x, y));
                 }
             }
         }
+        flashed.len()
+    }
+}
+
+/// Flash counts of a simulation run until its state repeats. The states
+/// are deterministic, so from then on the flashes are periodic.
+struct History {
+    /// total_flashes[t] is the number of flashes during the first t steps.
+    total_flashes: Vec<u64>,
+    cycle_start: usize,
+    cycle_len: usize,
+    num_octopuses: usize,
+}
+
+impl History {
+    fn record(mut octopuses: Octopuses, max_steps: usize) -> Result<Self> {
+        let mut seen = HashMap::new();
+        let mut total_flashes = vec![0];
+        let mut step = 0;
+        let cycle_start = loop {
+            if let Some(prev) = seen.insert(octopuses.energy_levels.clone(), step) {
+                break prev;
+            }
+            ensure!(step < max_steps, "no cycle within {} steps", max_steps);
+            let flashes = octopuses.step() as u64;
+            total_flashes.push(total_flashes[step] + flashes);
+            step += 1;
+        };
+        Ok(Self {
+            total_flashes,
+            cycle_start,
+            cycle_len: step - cycle_start,
+            num_octopuses: octopuses.energy_levels.len(),
+        })
+    }
+
+    fn flashes_after(&self, steps: u64) -> u64 {
+        let (start, len) = (self.cycle_start as u64, self.cycle_len as u64);
+        if steps <= start + len {
+            return self.total_flashes[steps as usize];
+        }
+        let per_cycle =
+            self.total_flashes[(start + len) as usize] - self.total_flashes[start as usize];
+        let (cycles, rest) = ((steps - start) / len, (steps - start) % len);
+        cycles * per_cycle + self.total_flashes[(start + rest) as usize]
+    }
+
+    /// The first step in which all octopuses flash. Every step occurs within
+    /// the recorded history or repeats one from it.
+    fn first_synchronized(&self) -> Option<usize> {
+        let flashes = self.total_flashes.iter().tuple_windows().map(|(a, b)| b - a);
+        flashes
+            .zip(1..)
+            .find(|(f, _)| *f == self.num_octopuses as u64)
+            .map(|(_, step)| step)
+    }
+}
+
+/// Runs both step implementations for a number of steps on a random n by n
+/// grid, checking that they flash identically.
+fn bench(n: usize, steps: usize, neighborhood: Neighborhood, threshold: u8) -> Result<()> {
+    let mut state = n as u64;
+    let energy_levels = (0..n * n)
+        .map(|_| {
+            state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            ((state >> 33) % (threshold as u64 + 1)) as u8
+        })
+        .collect_vec();
+    let mut fast = Octopuses::new(n as isize, energy_levels.clone(), neighborhood, threshold);
+    let mut reference = Octopuses::new(n as isize, energy_levels, neighborhood, threshold);
+
+    let start = std::time::Instant::now();
+    let fast_flashes = (0..steps).map(|_| fast.step()).collect_vec();
+    let fast_time = start.elapsed();
+    let start = std::time::Instant::now();
+    let reference_flashes = (0..steps).map(|_| reference.step_reference()).collect_vec();
+    let reference_time = start.elapsed();
+
+    ensure!(fast_flashes == reference_flashes, "step implementations disagree");
+    ensure!(fast.energy_levels == reference.energy_levels, "step implementations disagree");
+    let total: usize = fast_flashes.iter().sum();
+    println!("{}x{}, {} steps, {} flashes", n, n, steps, total);
+    println!("bitset and queue: {:?}", fast_time);
+    println!("reference: {:?}", reference_time);
+    Ok(())
+}
+
+fn main() -> Result<()> {
+    let mut neighborhood = Neighborhood::Eight;
+    let mut threshold = 9;
+    let mut targets = vec![100];
+    let mut max_steps = 1_000_000;
+    let mut bench_size = None;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().with_context(|| format!("{} needs a value", arg));
+        match arg.as_str() {
+            "--neighborhood" => neighborhood = value()?.parse()?,
+            "--threshold" => threshold = value()?.parse()?,
+            "--steps" => targets = value()?.split(',').map(str::parse).try_collect()?,
+            "--max-steps" => max_steps = value()?.parse()?,
+            "--bench" => bench_size = Some(value()?.parse()?),
+            _ => bail!("unknown argument {:?}", arg),
+        }
     }
 
+    ensure!(threshold < u8::MAX, "threshold must be below {}", u8::MAX);
+    if let Some(n) = bench_size {
+        return bench(
+            n,
+            targets.iter().copied().max().unwrap_or(0) as usize,
+            neighborhood,
+            threshold,
+        );
+    }
+    let input = std::fs::read_to_string("inputs/day11.txt")?;
+    let start = std::time::Instant::now();
+    let width = input.lines().next().context("no lines")?.len() as isize;
+    let energy_levels = input
+        .lines()
+        .flat_map(|line| line.trim().bytes().map(|b| b - b'0'))
+        .collect_vec();
+    let octopuses = Octopuses::new(width, energy_levels, neighborhood, threshold);
+
+    let history = History::record(octopuses, max_steps)?;
     println!("time: {:?}", start.elapsed());
-    println!("part1: {}", total100 + 100usize.saturating_sub(step) / 9);
-    println!("part2: {}", step);
+    if targets == [100] {
+        println!("part1: {}", history.flashes_after(100));
+        match history.first_synchronized() {
+            Some(step) => println!("part2: {}", step),
+            None => println!("part2: never synchronizes"),
+        }
+    } else {
+        for steps in &targets {
+            println!("after {} steps: {}", steps, history.flashes_after(*steps));
+        }
+        println!("cycle of {} steps from step {}", history.cycle_len, history.cycle_start);
+    }
     Ok(())
 }
@@ -1,78 +1,312 @@
 use std::borrow::Cow;
+use std::ops::ControlFlow;
+use std::str::FromStr;
 
-use anyhow::{Context, Ok, Result};
+use anyhow::{bail, ensure, Context, Result};
 use hashbrown::HashMap;
-use itertools::Either;
+use itertools::{Either, Itertools};
 
 const START: usize = 0;
 const END: usize = 1;
 
+/// How often caves may be visited, big caves being unrestricted and start
+/// never being revisited.
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum VisitPolicy {
+    /// Small caves once, except for k of them that may be visited twice.
+    TwiceForK(u32),
+    /// Small caves once, plus a number of extra visits shared by all caves.
+    Budget(u32),
+    /// Small caves once, except for the listed ones.
+    PerCave(Vec<(String, u32)>),
+}
+
+impl FromStr for VisitPolicy {
+    type Err = anyhow::Error;
+
+    /// Parses `once`, `twice:k`, `budget:b` or `max:cave=n,cave=n,...`.
+    fn from_str(s: &str) -> Result<Self> {
+        let (kind, arg) = s.split_once(':').unwrap_or((s, ""));
+        Ok(match kind {
+            "once" if arg.is_empty() => VisitPolicy::TwiceForK(0),
+            "twice" => VisitPolicy::TwiceForK(arg.parse()?),
+            "budget" => VisitPolicy::Budget(arg.parse()?),
+            "max" => VisitPolicy::PerCave(
+                arg.split(',')
+                    .map(|cave| {
+                        let (name, max) = cave.split_once('=').context("expected cave=n")?;
+                        Ok::<_, anyhow::Error>((name.to_string(), max.parse()?))
+                    })
+                    .try_collect()?,
+            ),
+            _ => bail!("unknown visit policy {:?}", s),
+        })
+    }
+}
+
+/// A visit policy resolved against a cave system. A cave may be entered
+/// while it has fewer than `max_visits` visits, or by spending one of
+/// `spare` extra visits as long as it then has at most `max_extra` more.
+struct VisitRules {
+    max_visits: Vec<u32>,
+    spare: u32,
+    max_extra: u32,
+}
+
+impl VisitRules {
+    /// Returns the spare visits left after entering `next`, if allowed.
+    fn enter(&self, next: usize, visits: &[u32], spare: u32) -> Option<u32> {
+        let max = self.max_visits[next];
+        if visits[next] < max {
+            Some(spare)
+        } else if next != START && spare > 0 && visits[next] < max + self.max_extra {
+            Some(spare - 1)
+        } else {
+            None
+        }
+    }
+}
+
+struct Caves<'a> {
+    names: Vec<&'a str>,
+    big: Vec<bool>,
+    edges: Vec<Vec<usize>>,
+}
+
+impl<'a> Caves<'a> {
+    fn parse(input: &'a str) -> Result<Self> {
+        let mut node_ids: HashMap<&str, usize> =
+            [("start", START), ("end", END)].into_iter().collect();
+        let mut caves = Caves {
+            names: vec!["start", "end"],
+            big: vec![false, false],
+            edges: vec![Vec::new(), Vec::new()],
+        };
+        for line in input.lines() {
+            let (a, b) = line.split_once('-').context("line contains no edge")?;
+            let [an, bn] = [a, b].map(|name| {
+                *node_ids.entry(name).or_insert_with(|| {
+                    caves.names.push(name);
+                    caves.big.push(name.chars().all(|c| c.is_ascii_uppercase()));
+                    caves.edges.push(Vec::new());
+                    caves.names.len() - 1
+                })
+            });
+            caves.edges[an].push(bn);
+            caves.edges[bn].push(an);
+        }
+        Ok(caves)
+    }
+
+    fn id(&self, name: &str) -> Option<usize> {
+        self.names.iter().position(|n| *n == name)
+    }
+
+    /// Two connected big caves can be bounced between indefinitely, making the
+    /// number of paths unbounded.
+    fn check_bounded(&self) -> Result<()> {
+        for (a, neighbors) in self.edges.iter().enumerate().filter(|(a, _)| self.big[*a]) {
+            if let Some(b) = neighbors.iter().find(|b| self.big[**b]) {
+                let (a, b) = (self.names[a], self.names[*b]);
+                bail!("big caves {} and {} are connected, allowing infinitely many paths", a, b);
+            }
+        }
+        Ok(())
+    }
+
+    fn rules(&self, policy: &VisitPolicy) -> Result<VisitRules> {
+        let small = |c: usize| if self.big[c] { u32::MAX } else { 1 };
+        let mut max_visits = (0..self.names.len()).map(small).collect_vec();
+        let (spare, max_extra) = match policy {
+            VisitPolicy::TwiceForK(k) => (*k, 1),
+            VisitPolicy::Budget(b) => (*b, *b),
+            VisitPolicy::PerCave(limits) => {
+                for (name, max) in limits {
+                    let id = self.id(name).with_context(|| format!("unknown cave {:?}", name))?;
+                    ensure!(!self.big[id], "big cave {} has no visit limit", name);
+                    ensure!(id != START, "start cannot be revisited");
+                    max_visits[id] = *max;
+                }
+                (0, 0)
+            },
+        };
+        Ok(VisitRules {
+            max_visits,
+            spare,
+            max_extra,
+        })
+    }
+
+    /// Edges between small caves only, for counting paths. Requires the caves
+    /// to be bounded.
+    fn collapsed_edges(&self) -> Vec<Vec<usize>> {
+        // Replace each edge to a big node with all nodes it connects to.
+        let mut edgelist = self.edges.clone();
+        for node in 0..edgelist.len() {
+            edgelist[node] = core::mem::take(&mut edgelist[node])
+                .into_iter()
+                .flat_map(|next| {
+                    if self.big[next] {
+                        Either::Left(edgelist[next].iter().copied())
+                    } else {
+                        Either::Right([next].into_iter())
+                    }
+                })
+                .collect();
+        }
+        edgelist
+    }
+
+    /// Calls `f` with every path from start to end, as a list of caves, until
+    /// it breaks. Requires the caves to be bounded.
+    fn for_each_path<F>(&self, rules: &VisitRules, mut f: F) -> ControlFlow<()>
+    where
+        F: FnMut(&[usize]) -> ControlFlow<()>,
+    {
+        let mut visits = vec![0; self.names.len()];
+        visits[START] = 1;
+        let mut path = vec![START];
+        self.extend_paths(rules, &mut path, &mut visits, rules.spare, &mut f)
+    }
+
+    fn extend_paths<F>(
+        &self,
+        rules: &VisitRules,
+        path: &mut Vec<usize>,
+        visits: &mut [u32],
+        spare: u32,
+        f: &mut F,
+    ) -> ControlFlow<()>
+    where
+        F: FnMut(&[usize]) -> ControlFlow<()>,
+    {
+        let from = *path.last().unwrap();
+        if from == END {
+            return f(path);
+        }
+        for next in self.edges[from].iter().copied() {
+            let Some(spare) = rules.enter(next, visits, spare) else {
+                continue;
+            };
+            visits[next] += 1;
+            path.push(next);
+            let flow = self.extend_paths(rules, path, visits, spare, f);
+            path.pop();
+            visits[next] -= 1;
+            flow?;
+        }
+        ControlFlow::Continue(())
+    }
+
+    /// Graphviz rendering of the cave system with big caves highlighted.
+    fn to_dot(&self) -> String {
+        let mut dot = String::from("graph caves {\n");
+        for (id, name) in self.names.iter().enumerate() {
+            let style = match id {
+                START | END => "shape=box",
+                _ if self.big[id] => "style=filled, fillcolor=gold, shape=doublecircle",
+                _ => "shape=circle",
+            };
+            dot += &format!("    \"{}\" [{}];\n", name, style);
+        }
+        for (a, neighbors) in self.edges.iter().enumerate() {
+            for b in neighbors.iter().copied().filter(|b| a < *b) {
+                dot += &format!("    \"{}\" -- \"{}\";\n", self.names[a], self.names[b]);
+            }
+        }
+        dot + "}\n"
+    }
+}
+
 fn count_paths<'a>(
     from: usize,
     edges: &[Vec<usize>],
-    seen: &mut [bool],
-    allow_twice: bool,
-    cache: &mut HashMap<(usize, Cow<'a, [bool]>, bool), u64>,
+    rules: &VisitRules,
+    visits: &mut [u32],
+    spare: u32,
+    cache: &mut HashMap<(usize, Cow<'a, [u32]>, u32), u64>,
 ) -> u64 {
     if from == END {
         return 1;
-    } else if let Some(ret) = cache.get(&(from, Cow::Borrowed(seen), allow_twice)) {
+    } else if let Some(ret) = cache.get(&(from, Cow::Borrowed(visits), spare)) {
         return *ret;
     }
 
     let mut total = 0;
     for next in edges[from].iter().copied() {
-        if !seen[next] {
-            seen[next] = true;
-            total += count_paths(next, edges, seen, allow_twice, cache);
-            seen[next] = false;
-        } else if allow_twice && next != START {
-            total += count_paths(next, edges, seen, false, cache);
+        if let Some(spare) = rules.enter(next, visits, spare) {
+            visits[next] += 1;
+            total += count_paths(next, edges, rules, visits, spare, cache);
+            visits[next] -= 1;
         }
     }
-    cache.insert((from, seen.iter().copied().collect(), allow_twice), total);
+    cache.insert((from, visits.iter().copied().collect(), spare), total);
     total
 }
 
+fn num_paths(caves: &Caves, edges: &[Vec<usize>], policy: &VisitPolicy) -> Result<u64> {
+    let rules = caves.rules(policy)?;
+    let mut visits = vec![0; caves.names.len()];
+    visits[START] = 1;
+    Ok(count_paths(START, edges, &rules, &mut visits, rules.spare, &mut HashMap::new()))
+}
+
 fn main() -> Result<()> {
+    let mut list_paths = false;
+    let mut policy = None;
+    let mut through = Vec::new();
+    let mut limit = None;
+    let mut dot_path = None;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().with_context(|| format!("{} needs a value", arg));
+        match arg.as_str() {
+            "--paths" => list_paths = true,
+            "--rules" => policy = Some(value()?.parse::<VisitPolicy>()?),
+            "--through" => through.push(value()?),
+            "--limit" => limit = Some(value()?.parse::<usize>()?),
+            "--dot" => dot_path = Some(value()?),
+            _ => bail!("unknown argument {:?}", arg),
+        }
+    }
+
     let input = std::fs::read_to_string("inputs/day12.txt")?;
     let start_time = std::time::Instant::now();
-    let mut node_ids: HashMap<&str, usize> = [("start", START), ("end", END)].into_iter().collect();
-    let mut edgelist: Vec<Vec<usize>> = vec![Vec::new(), Vec::new()];
-    let mut big_node: Vec<bool> = vec![false, false];
-    for line in input.lines() {
-        let (a, b) = line.split_once('-').context("line contains no edge")?;
-        let [an, bn] = [a, b].map(|name| {
-            *node_ids.entry(name).or_insert_with(|| {
-                let ret = edgelist.len();
-                edgelist.push(Vec::new());
-                big_node.push(name.chars().all(|c| c.is_ascii_uppercase()));
-                ret
-            })
+    let caves = Caves::parse(&input)?;
+    if let Some(path) = dot_path {
+        std::fs::write(path, caves.to_dot())?;
+    }
+    caves.check_bounded()?;
+
+    if list_paths {
+        let through: Vec<usize> = through
+            .iter()
+            .map(|name| caves.id(name).with_context(|| format!("unknown cave {:?}", name)))
+            .try_collect()?;
+        let rules = caves.rules(&policy.unwrap_or(VisitPolicy::TwiceForK(0)))?;
+        let mut num_listed = 0;
+        let _ = caves.for_each_path(&rules, |path| {
+            if limit.is_some_and(|l| num_listed >= l) {
+                return ControlFlow::Break(());
+            }
+            if through.iter().all(|c| path.contains(c)) {
+                println!("{}", path.iter().map(|c| caves.names[*c]).join(","));
+                num_listed += 1;
+            }
+            ControlFlow::Continue(())
         });
-        edgelist[an].push(bn);
-        edgelist[bn].push(an);
-    }
-
-    // Two big nodes can't be connected, it would make the answer unbounded.
-    // Replace each edge to a big node with all nodes it connects to.
-    for node in 0..edgelist.len() {
-        edgelist[node] = core::mem::take(&mut edgelist[node])
-            .into_iter()
-            .flat_map(|next| {
-                if big_node[next] {
-                    Either::Left(edgelist[next].iter().copied())
-                } else {
-                    Either::Right([next].into_iter())
-                }
-            })
-            .collect();
+        return Ok(());
     }
 
-    let mut seen = vec![false; node_ids.len()];
-    seen[START] = true;
-    let part1 = count_paths(START, &edgelist, &mut seen, false, &mut HashMap::new());
-    let part2 = count_paths(START, &edgelist, &mut seen, true, &mut HashMap::new());
+    let edgelist = caves.collapsed_edges();
+    if let Some(policy) = policy {
+        let total = num_paths(&caves, &edgelist, &policy)?;
+        println!("time: {:?}", start_time.elapsed());
+        println!("{}", total);
+        return Ok(());
+    }
+    let part1 = num_paths(&caves, &edgelist, &VisitPolicy::TwiceForK(0))?;
+    let part2 = num_paths(&caves, &edgelist, &VisitPolicy::TwiceForK(1))?;
     println!("time: {:?}", start_time.elapsed());
     println!("{}", part1);
     println!("{}", part2);
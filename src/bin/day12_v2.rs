@@ -0,0 +1,271 @@
+use std::borrow::Cow;
+
+use anyhow::{bail, ensure, Context, Result};
+use hashbrown::HashMap;
+use itertools::{Either, Itertools};
+
+
+/*
+    Same counting as day12 but with the set of visited small caves stored as
+    a u64 bitmask, so a memo key is two words instead of a cloned Vec<bool>.
+    Big caves are collapsed away first, leaving at most 64 small caves. For
+    up to DENSE_MAX_CAVES caves the memo is a flat table indexed by (cave,
+    visited, twice allowed), otherwise a hash map keyed on packed integers.
+    The number of states can grow exponentially with the number of small
+    caves, so counting gives up once a memo holds MAX_STATES of them.
+*/
+
+const START: usize = 0;
+const END: usize = 1;
+const DENSE_MAX_CAVES: usize = 12;
+const UNKNOWN: u64 = u64::MAX;
+const MAX_STATES: usize = 4_000_000;
+
+/// Small caves with the edges between them, an edge via a big cave being
+/// repeated once per big cave.
+struct SmallCaves {
+    edges: Vec<Vec<usize>>,
+}
+
+impl SmallCaves {
+    fn parse(input: &str) -> Result<Self> {
+        let mut node_ids: HashMap<&str, usize> =
+            [("start", START), ("end", END)].into_iter().collect();
+        let mut edgelist: Vec<Vec<usize>> = vec![Vec::new(), Vec::new()];
+        let mut big_node: Vec<bool> = vec![false, false];
+        for line in input.lines() {
+            let (a, b) = line.split_once('-').context("line contains no edge")?;
+            let [an, bn] = [a, b].map(|name| {
+                *node_ids.entry(name).or_insert_with(|| {
+                    edgelist.push(Vec::new());
+                    big_node.push(name.chars().all(|c| c.is_ascii_uppercase()));
+                    edgelist.len() - 1
+                })
+            });
+            edgelist[an].push(bn);
+            edgelist[bn].push(an);
+        }
+        Self::collapse(&edgelist, &big_node)
+    }
+
+    /// Replaces each edge to a big cave with all caves it connects to and
+    /// renumbers the small caves, keeping start and end in place.
+    fn collapse(edgelist: &[Vec<usize>], big_node: &[bool]) -> Result<Self> {
+        for (_, neighbors) in edgelist.iter().enumerate().filter(|(a, _)| big_node[*a]) {
+            let bounded = !neighbors.iter().any(|b| big_node[*b]);
+            ensure!(bounded, "connected big caves allow infinitely many paths");
+        }
+        let small = (0..edgelist.len()).filter(|c| !big_node[*c]).collect_vec();
+        ensure!(small.len() <= 64, "{} small caves, at most 64 supported", small.len());
+        let mut new_id = vec![usize::MAX; edgelist.len()];
+        for (i, c) in small.iter().enumerate() {
+            new_id[*c] = i;
+        }
+
+        let edges = small
+            .iter()
+            .map(|c| {
+                edgelist[*c]
+                    .iter()
+                    .flat_map(|next| {
+                        if big_node[*next] {
+                            Either::Left(edgelist[*next].iter().copied())
+                        } else {
+                            Either::Right([*next].into_iter())
+                        }
+                    })
+                    .map(|next| new_id[next])
+                    .collect()
+            })
+            .collect();
+        Ok(Self { edges })
+    }
+
+    fn len(&self) -> usize {
+        self.edges.len()
+    }
+}
+
+enum Memo {
+    /// Totals indexed by ((seen << 1 | allow_twice) * num_caves + from).
+    Dense { num_caves: usize, totals: Vec<u64> },
+    /// Totals keyed by seen and (from << 1 | allow_twice).
+    Sparse(HashMap<(u64, u32), u64>),
+}
+
+impl Memo {
+    fn new(num_caves: usize) -> Self {
+        if num_caves <= DENSE_MAX_CAVES {
+            Memo::Dense {
+                num_caves,
+                totals: vec![UNKNOWN; num_caves << (num_caves + 1)],
+            }
+        } else {
+            Memo::Sparse(HashMap::new())
+        }
+    }
+
+    fn get(&self, from: usize, seen: u64, allow_twice: bool) -> Option<u64> {
+        match self {
+            Memo::Dense { num_caves, totals } => {
+                let total =
+                    totals[((seen as usize) << 1 | allow_twice as usize) * num_caves + from];
+                (total != UNKNOWN).then_some(total)
+            },
+            Memo::Sparse(map) => map.get(&(seen, (from as u32) << 1 | allow_twice as u32)).copied(),
+        }
+    }
+
+    /// Returns None if the memo is full.
+    fn insert(&mut self, from: usize, seen: u64, allow_twice: bool, total: u64) -> Option<()> {
+        match self {
+            Memo::Dense { num_caves, totals } => {
+                totals[((seen as usize) << 1 | allow_twice as usize) * *num_caves + from] = total;
+            },
+            Memo::Sparse(map) => {
+                if map.len() >= MAX_STATES {
+                    return None;
+                }
+                map.insert((seen, (from as u32) << 1 | allow_twice as u32), total);
+            },
+        }
+        Some(())
+    }
+}
+
+fn count_paths(
+    from: usize,
+    edges: &[Vec<usize>],
+    seen: u64,
+    allow_twice: bool,
+    memo: &mut Memo,
+) -> Option<u64> {
+    if from == END {
+        return Some(1);
+    } else if let Some(ret) = memo.get(from, seen, allow_twice) {
+        return Some(ret);
+    }
+
+    let mut total = 0;
+    for next in edges[from].iter().copied() {
+        if seen & (1 << next) == 0 {
+            total += count_paths(next, edges, seen | 1 << next, allow_twice, memo)?;
+        } else if allow_twice && next != START {
+            total += count_paths(next, edges, seen, false, memo)?;
+        }
+    }
+    memo.insert(from, seen, allow_twice, total)?;
+    Some(total)
+}
+
+fn num_paths(caves: &SmallCaves, allow_twice: bool) -> Result<u64> {
+    let mut memo = Memo::new(caves.len());
+    count_paths(START, &caves.edges, 1 << START, allow_twice, &mut memo)
+        .with_context(|| format!("more than {} memoized states", MAX_STATES))
+}
+
+/// The memoization of day12, for comparison, with the same limit on states.
+fn count_paths_reference<'a>(
+    from: usize,
+    edges: &[Vec<usize>],
+    seen: &mut [bool],
+    allow_twice: bool,
+    cache: &mut HashMap<(usize, Cow<'a, [bool]>, bool), u64>,
+) -> Option<u64> {
+    if from == END {
+        return Some(1);
+    } else if let Some(ret) = cache.get(&(from, Cow::Borrowed(seen), allow_twice)) {
+        return Some(*ret);
+    } else if cache.len() >= MAX_STATES {
+        return None;
+    }
+
+    let mut total = 0;
+    for next in edges[from].iter().copied() {
+        if !seen[next] {
+            seen[next] = true;
+            let paths = count_paths_reference(next, edges, seen, allow_twice, cache);
+            seen[next] = false;
+            total += paths?;
+        } else if allow_twice && next != START {
+            total += count_paths_reference(next, edges, seen, false, cache)?;
+        }
+    }
+    cache.insert((from, seen.iter().copied().collect(), allow_twice), total);
+    Some(total)
+}
+
+/// Generates a chain of m clusters between start and end. Each cluster has
+/// k small caves on a path, all connected to a big cave, and its last small
+/// cave leads to the first of the next cluster.
+fn generate(m: usize, k: usize) -> String {
+    let small = |c: usize, i: usize| format!("c{}s{}", c, i);
+    let mut lines = vec![format!("start-{}", small(0, 0))];
+    for c in 0..m {
+        for i in 0..k {
+            lines.push(format!("B{}-{}", c, small(c, i)));
+            if i + 1 < k {
+                lines.push(format!("{}-{}", small(c, i), small(c, i + 1)));
+            }
+        }
+        let next = if c + 1 < m {
+            small(c + 1, 0)
+        } else {
+            "end".to_string()
+        };
+        lines.push(format!("{}-{}", small(c, k - 1), next));
+    }
+    lines.join("\n")
+}
+
+fn bench(m: usize, k: usize) -> Result<()> {
+    let caves = SmallCaves::parse(&generate(m, k))?;
+    println!(
+        "{} small caves, {} edges",
+        caves.len(),
+        caves.edges.iter().map(Vec::len).sum::<usize>() / 2
+    );
+    for allow_twice in [false, true] {
+        let start = std::time::Instant::now();
+        let fast = num_paths(&caves, allow_twice)?;
+        let fast_time = start.elapsed();
+
+        let start = std::time::Instant::now();
+        let mut seen = vec![false; caves.len()];
+        seen[START] = true;
+        let reference =
+            count_paths_reference(START, &caves.edges, &mut seen, allow_twice, &mut HashMap::new())
+                .with_context(|| format!("reference: more than {} memoized states", MAX_STATES))?;
+        let reference_time = start.elapsed();
+
+        ensure!(fast == reference, "bitmask count {} differs from {}", fast, reference);
+        println!("twice {}: {} paths", allow_twice, fast);
+        println!("    bitmask: {:?}", fast_time);
+        println!("    reference: {:?}", reference_time);
+    }
+    Ok(())
+}
+
+fn main() -> Result<()> {
+    let args = std::env::args().skip(1).collect_vec();
+    match args.as_slice() {
+        [] => (),
+        [flag, m] if flag == "--bench" => return bench(m.parse()?, 3),
+        [flag, m, k] if flag == "--bench" => {
+            let k = k.parse()?;
+            ensure!(k > 0, "clusters need at least one cave");
+            return bench(m.parse()?, k);
+        },
+        _ => bail!("usage: day12_v2 [--bench <clusters> [<cluster size>]]"),
+    }
+
+    let input = std::fs::read_to_string("inputs/day12.txt")?;
+    let start_time = std::time::Instant::now();
+    let caves = SmallCaves::parse(&input)?;
+    let part1 = num_paths(&caves, false)?;
+    let part2 = num_paths(&caves, true)?;
+    println!("time: {:?}", start_time.elapsed());
+    println!("{}", part1);
+    println!("{}", part2);
+    Ok(())
+}
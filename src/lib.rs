@@ -0,0 +1,2 @@
+pub mod seven_segment;
+pub mod union_find;
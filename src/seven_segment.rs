@@ -0,0 +1,7 @@
+/// Lit segments of each digit on a standard display, bit i being segment
+/// 'a' + i.
+#[rustfmt::skip]
+pub const FONT: [u8; 10] = [
+    0b1110111, 0b0100100, 0b1011101, 0b1101101, 0b0101110,
+    0b1101011, 0b1111011, 0b0100101, 0b1111111, 0b1101111,
+];
@@ -0,0 +1,145 @@
+use std::io::BufRead;
+
+
+/// Per-component data that can be combined when two components are joined.
+pub trait Merge {
+    fn merge(&mut self, other: Self);
+}
+
+/// Disjoint-set forest with union by size and path halving. Every root
+/// carries the merged data of all elements in its component.
+#[derive(Clone, Debug, Default)]
+pub struct UnionFind<T> {
+    parent: Vec<usize>,
+    size: Vec<usize>,
+    data: Vec<Option<T>>,
+}
+
+impl<T: Merge> UnionFind<T> {
+    pub fn new() -> Self {
+        Self {
+            parent: Vec::new(),
+            size: Vec::new(),
+            data: Vec::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.parent.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.parent.is_empty()
+    }
+
+    pub fn make_set(&mut self, data: T) -> usize {
+        let idx = self.parent.len();
+        self.parent.push(idx);
+        self.size.push(1);
+        self.data.push(Some(data));
+        idx
+    }
+
+    pub fn find(&mut self, mut cur: usize) -> usize {
+        let mut parent = self.parent[cur];
+        while cur != parent {
+            let grandparent = self.parent[parent];
+            self.parent[cur] = grandparent;
+            cur = parent;
+            parent = grandparent;
+        }
+        cur
+    }
+
+    /// Joins the components of a and b, returning the new root.
+    pub fn union(&mut self, mut a: usize, mut b: usize) -> usize {
+        a = self.find(a);
+        b = self.find(b);
+        if a != b {
+            if self.size[a] < self.size[b] {
+                core::mem::swap(&mut a, &mut b);
+            }
+            self.parent[b] = a;
+            self.size[a] += self.size[b];
+            let merged = self.data[b].take().unwrap();
+            self.data[a].as_mut().unwrap().merge(merged);
+        }
+        a
+    }
+
+    /// Data of the component containing x.
+    pub fn get(&mut self, x: usize) -> &T {
+        let root = self.find(x);
+        self.data[root].as_ref().unwrap()
+    }
+
+    pub fn get_mut(&mut self, x: usize) -> &mut T {
+        let root = self.find(x);
+        self.data[root].as_mut().unwrap()
+    }
+
+    /// Root and data of every component.
+    pub fn components(&self) -> impl Iterator<Item = (usize, &T)> {
+        self.data.iter().enumerate().flat_map(|(i, d)| Some((i, d.as_ref()?)))
+    }
+
+    pub fn into_components(self) -> impl Iterator<Item = T> {
+        self.data.into_iter().flatten()
+    }
+}
+
+/// Labels 4-connected components of a grid read line by line. `classify`
+/// gets the (x, y) position and byte of each cell and returns its data if the
+/// cell is part of some component, or None for background. Each component is
+/// passed to `emit` once the first row below it that does not continue it
+/// has been read, so only the components touching the previous row are kept
+/// and the working memory is O(width).
+pub fn label_components<R, T, F, E>(reader: R, mut classify: F, mut emit: E) -> std::io::Result<()>
+where
+    R: BufRead,
+    T: Merge,
+    F: FnMut(usize, usize, u8) -> Option<T>,
+    E: FnMut(T),
+{
+    let mut union_find = UnionFind::new();
+    let mut prev_row: Vec<Option<usize>> = Vec::new();
+    let mut cur_row = Vec::new();
+    for (y, line) in reader.lines().enumerate() {
+        for (x, c) in line?.trim().bytes().enumerate() {
+            let Some(data) = classify(x, y, c) else {
+                cur_row.push(None);
+                continue;
+            };
+            let up = prev_row.get(x).copied().flatten();
+            let left = cur_row.last().copied().flatten();
+            let comp = match (up, left) {
+                (Some(u), Some(l)) => union_find.union(u, l),
+                (Some(u), None) => union_find.find(u),
+                (None, Some(l)) => l, // We just found the left neighbor.
+                (None, None) => {
+                    cur_row.push(Some(union_find.make_set(data)));
+                    continue;
+                },
+            };
+            union_find.get_mut(comp).merge(data);
+            cur_row.push(Some(comp));
+        }
+
+        // Move the components continuing in this row into a fresh forest,
+        // relabeling the row, and emit the rest.
+        let mut live = UnionFind::new();
+        let mut relabeled = vec![None; union_find.len()];
+        for label in cur_row.iter_mut().flatten() {
+            let root = union_find.find(*label);
+            *label = *relabeled[root]
+                .get_or_insert_with(|| live.make_set(union_find.data[root].take().unwrap()));
+        }
+        union_find.into_components().for_each(&mut emit);
+        union_find = live;
+
+        core::mem::swap(&mut cur_row, &mut prev_row);
+        cur_row.clear();
+    }
+    union_find.into_components().for_each(emit);
+    Ok(())
+}